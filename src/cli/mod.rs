@@ -1,9 +1,9 @@
-use std::{fmt::Write as _, process::ExitCode};
+use std::{env, fmt::Write as _, path::PathBuf, process::ExitCode, time::Duration};
 
 use anyhow::{Context, Result};
 use clap::Parser;
 
-use lune::Lune;
+use lune::{ErrorPolicy, Lune, TimeLimitAction};
 use tokio::{
     fs::read as read_to_vec,
     io::{stdin, AsyncReadExt},
@@ -20,6 +20,8 @@ use utils::{
     listing::{find_lune_scripts, sort_lune_scripts, write_lune_scripts_list},
 };
 
+const ENV_VAR_VIRTUAL_CLOCK: &str = "LUNE_VIRTUAL_CLOCK";
+
 /// A Luau script runner
 #[derive(Parser, Debug, Default, Clone)]
 #[command(version, long_about = None)]
@@ -29,6 +31,24 @@ pub struct Cli {
     script_path: Option<String>,
     /// Arguments to pass to the script, stored in process.args
     script_args: Vec<String>,
+    /// Record network requests and responses into a cassette file
+    #[clap(long, value_name = "PATH", conflicts_with = "net_replay")]
+    net_record: Option<PathBuf>,
+    /// Replay network responses from a cassette file, instead of sending requests
+    #[clap(long, value_name = "PATH")]
+    net_replay: Option<PathBuf>,
+    /// Use a virtual clock that skips ahead instantly, instead of waiting in real time - may also be enabled using LUNE_VIRTUAL_CLOCK=1
+    #[clap(long)]
+    virtual_clock: bool,
+    /// What to do when a thread throws an error that is not handled, either `continue` or `abort`
+    #[clap(long, value_name = "POLICY")]
+    on_error: Option<ErrorPolicy>,
+    /// Maximum number of seconds a thread may run without yielding, stopping infinite loops
+    #[clap(long, value_name = "SECS", value_parser = parse_time_limit)]
+    time_limit: Option<Duration>,
+    /// What to do when a thread runs for longer than the time limit, either `error` or `yield`
+    #[clap(long, value_name = "ACTION", requires = "time_limit")]
+    time_limit_action: Option<TimeLimitAction>,
     /// List scripts found inside of a nearby `lune` directory
     #[clap(long, short = 'l')]
     list: bool,
@@ -166,8 +186,23 @@ impl Cli {
             (file_display_name, file_contents)
         };
         // Create a new lune object with all globals & run the script
-        let result = Lune::new()
-            .with_args(self.script_args)
+        let mut lune = Lune::new().with_args(self.script_args);
+        if let Some(path) = self.net_record {
+            lune = lune.with_net_recording(path);
+        }
+        if let Some(path) = self.net_replay {
+            lune = lune.with_net_replay(path);
+        }
+        if self.virtual_clock || is_env_flag_enabled(ENV_VAR_VIRTUAL_CLOCK) {
+            lune = lune.with_virtual_clock();
+        }
+        if let Some(policy) = self.on_error {
+            lune = lune.with_error_policy(policy);
+        }
+        if let Some(limit) = self.time_limit {
+            lune = lune.with_time_limit(limit, self.time_limit_action.unwrap_or_default());
+        }
+        let result = lune
             .run(&script_display_name, strip_shebang(script_contents))
             .await;
         Ok(match result {
@@ -179,3 +214,21 @@ impl Cli {
         })
     }
 }
+
+fn is_env_flag_enabled(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| {
+        matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
+fn parse_time_limit(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs > 0.0 => Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()),
+        _ => Err(format!(
+            "Invalid time limit '{s}' - expected a positive number of seconds"
+        )),
+    }
+}
@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, time::SystemTime};
 
 use mlua::prelude::*;
 
@@ -6,7 +6,7 @@ use chrono::prelude::*;
 use chrono::DateTime as ChronoDateTime;
 use chrono_lc::LocaleDate;
 
-use crate::lune::util::TableBuilder;
+use crate::lune::{scheduler::Scheduler, util::TableBuilder};
 
 mod error;
 mod values;
@@ -28,7 +28,12 @@ pub fn create(lua: &Lua) -> LuaResult<LuaTable> {
         .with_function("fromUnixTimestamp", |_, timestamp| {
             Ok(DateTime::from_unix_timestamp_float(timestamp)?)
         })?
-        .with_function("now", |_, ()| Ok(DateTime::now()))?
+        .with_function("now", |lua, ()| {
+            let sched = lua
+                .app_data_ref::<&Scheduler>()
+                .expect("Lua struct is missing scheduler");
+            Ok(DateTime::from_system_time(sched.clock_system_time()))
+        })?
         .build_readonly()
 }
 
@@ -45,12 +50,15 @@ pub struct DateTime {
 
 impl DateTime {
     /**
-        Creates a new `DateTime` struct representing the current moment in time.
+        Creates a new `DateTime` struct from the given `system_time`.
 
-        See [`chrono::DateTime::now`] for additional details.
+        This is used instead of [`chrono::DateTime::now`] to get the
+        current moment in time, so that it follows the scheduler clock.
     */
-    pub fn now() -> Self {
-        Self { inner: Utc::now() }
+    pub fn from_system_time(system_time: SystemTime) -> Self {
+        Self {
+            inner: system_time.into(),
+        }
     }
 
     /**
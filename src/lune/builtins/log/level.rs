@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr};
+
+use console::{style, StyledObject};
+use mlua::prelude::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn get_all() -> Vec<Self> {
+        vec![
+            Self::Trace,
+            Self::Debug,
+            Self::Info,
+            Self::Warn,
+            Self::Error,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+
+    pub fn styled_label(&self) -> StyledObject<&'static str> {
+        let label = match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO ",
+            Self::Warn => "WARN ",
+            Self::Error => "ERROR",
+        };
+        let styled = style(label).for_stderr();
+        match self {
+            Self::Trace => styled.dim(),
+            Self::Debug => styled.blue(),
+            Self::Info => styled.green(),
+            Self::Warn => styled.yellow(),
+            Self::Error => styled.red(),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::get_all()
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "Invalid log level '{s}', valid levels are: {}",
+                    Self::get_all()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+impl<'lua> FromLua<'lua> for LogLevel {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match &value {
+            LuaValue::String(s) => {
+                s.to_str()?
+                    .parse()
+                    .map_err(|message| LuaError::FromLuaConversionError {
+                        from: "string",
+                        to: "LogLevel",
+                        message: Some(message),
+                    })
+            }
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "LogLevel",
+                message: None,
+            }),
+        }
+    }
+}
+
+/**
+    The minimum level that a log record must have to be written,
+    or no level at all if logging has been turned off completely.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogFilter(pub Option<LogLevel>);
+
+impl LogFilter {
+    pub fn allows(&self, level: LogLevel) -> bool {
+        self.0.is_some_and(|min| level >= min)
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self(Some(LogLevel::default()))
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("off") {
+            Ok(Self(None))
+        } else {
+            s.parse().map(|level| Self(Some(level)))
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for LogFilter {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match &value {
+            LuaValue::String(s) if s.to_str()?.trim().eq_ignore_ascii_case("off") => Ok(Self(None)),
+            _ => Ok(Self(Some(LogLevel::from_lua(value, lua)?))),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    cell::RefCell,
+    env,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use mlua::prelude::*;
+
+use crate::lune::util::{progress::suspend_progress_bars, TableBuilder};
+
+mod level;
+mod record;
+
+use level::{LogFilter, LogLevel};
+use record::{LogFormat, LogRecord};
+
+const ENV_LOG_LEVEL: &str = "LUNE_LOG";
+const ENV_LOG_FORMAT: &str = "LUNE_LOG_FORMAT";
+
+/**
+    Shared state for all of the functions in the log builtin.
+
+    Tasks are identified using a table with weak keys, which maps
+    each lua thread that has logged anything to a sequential id.
+*/
+#[derive(Debug)]
+struct LogState {
+    filter: LogFilter,
+    format: LogFormat,
+    tasks: LuaRegistryKey,
+    next_task_id: usize,
+}
+
+impl LogState {
+    fn from_env(lua: &Lua) -> LuaResult<Self> {
+        let filter = match env::var(ENV_LOG_LEVEL) {
+            Ok(s) if !s.trim().is_empty() => s.parse().map_err(|e| {
+                LuaError::RuntimeError(format!(
+                    "Invalid {ENV_LOG_LEVEL} environment variable - {e}"
+                ))
+            })?,
+            _ => LogFilter::default(),
+        };
+        let format = match env::var(ENV_LOG_FORMAT) {
+            Ok(s) if !s.trim().is_empty() => s.parse().map_err(|e| {
+                LuaError::RuntimeError(format!(
+                    "Invalid {ENV_LOG_FORMAT} environment variable - {e}"
+                ))
+            })?,
+            _ => LogFormat::default(),
+        };
+        let tasks = lua.create_table()?;
+        tasks.set_metatable(Some(
+            TableBuilder::new(lua)?.with_value("__mode", "k")?.build()?,
+        ));
+        Ok(Self {
+            filter,
+            format,
+            tasks: lua.create_registry_value(tasks)?,
+            next_task_id: 0,
+        })
+    }
+
+    fn current_task_id(&mut self, lua: &Lua) -> LuaResult<usize> {
+        let thread = lua.current_thread();
+        let tasks: LuaTable = lua.registry_value(&self.tasks)?;
+        if let Some(id) = tasks.raw_get::<_, Option<usize>>(thread.clone())? {
+            return Ok(id);
+        }
+        self.next_task_id += 1;
+        tasks.raw_set(thread, self.next_task_id)?;
+        Ok(self.next_task_id)
+    }
+}
+
+type LogStateRef = Rc<RefCell<LogState>>;
+
+pub fn create(lua: &'static Lua) -> LuaResult<LuaTable<'static>> {
+    let state: LogStateRef = Rc::new(RefCell::new(LogState::from_env(lua)?));
+    let log_fn = |level: LogLevel| {
+        let state = Rc::clone(&state);
+        move |lua: &'static Lua, (message, fields)| log(lua, &state, level, message, fields)
+    };
+    let state_enabled = Rc::clone(&state);
+    let state_level = Rc::clone(&state);
+    let state_format = Rc::clone(&state);
+    TableBuilder::new(lua)?
+        .with_function("trace", log_fn(LogLevel::Trace))?
+        .with_function("debug", log_fn(LogLevel::Debug))?
+        .with_function("info", log_fn(LogLevel::Info))?
+        .with_function("warn", log_fn(LogLevel::Warn))?
+        .with_function("error", log_fn(LogLevel::Error))?
+        .with_function("enabled", move |_, level: LogLevel| {
+            Ok(state_enabled.borrow().filter.allows(level))
+        })?
+        .with_function("setLevel", move |_, filter: LogFilter| {
+            state_level.borrow_mut().filter = filter;
+            Ok(())
+        })?
+        .with_function("setFormat", move |_, format: LogFormat| {
+            state_format.borrow_mut().format = format;
+            Ok(())
+        })?
+        .build_readonly()
+}
+
+/**
+    Writes a log record to stderr, if its level is not filtered out.
+
+    Note that this is intentionally not async - the current lua thread is used to
+    tell tasks apart, and this also lets logging work in contexts that can not yield.
+*/
+fn log<'lua>(
+    lua: &'lua Lua,
+    state: &LogStateRef,
+    level: LogLevel,
+    message: LuaString<'lua>,
+    fields: Option<LuaTable<'lua>>,
+) -> LuaResult<()> {
+    let (format, task) = {
+        let mut state = state.borrow_mut();
+        if !state.filter.allows(level) {
+            return Ok(());
+        }
+        (state.format, state.current_task_id(lua)?)
+    };
+    let record = LogRecord::new(lua, level, task, message, fields)?;
+    suspend_progress_bars(|| {
+        let mut stderr = io::stderr().lock();
+        stderr.write_all(record.format(format).as_bytes())?;
+        stderr.flush()
+    })?;
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use std::{fmt::Write, str::FromStr};
+
+use chrono::{Local, SecondsFormat, Utc};
+use console::style;
+use mlua::prelude::*;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+use super::level::LogLevel;
+
+const LUA_DESERIALIZE_OPTIONS: LuaDeserializeOptions = LuaDeserializeOptions::new()
+    .sort_keys(true)
+    .deny_recursive_tables(false)
+    .deny_unsupported_types(false);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Invalid log format '{s}', valid formats are: human, json"
+            )),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for LogFormat {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match &value {
+            LuaValue::String(s) => {
+                s.to_str()?
+                    .parse()
+                    .map_err(|message| LuaError::FromLuaConversionError {
+                        from: "string",
+                        to: "LogFormat",
+                        message: Some(message),
+                    })
+            }
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "LogFormat",
+                message: None,
+            }),
+        }
+    }
+}
+
+/**
+    A single log record, ready to be written out in any format.
+*/
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: LogLevel,
+    pub task: usize,
+    pub message: String,
+    pub fields: Vec<(String, JsonValue)>,
+}
+
+impl LogRecord {
+    pub fn new<'lua>(
+        lua: &'lua Lua,
+        level: LogLevel,
+        task: usize,
+        message: LuaString<'lua>,
+        fields: Option<LuaTable<'lua>>,
+    ) -> LuaResult<Self> {
+        let mut record_fields = Vec::new();
+        if let Some(fields) = fields {
+            for pair in fields.pairs::<LuaValue, LuaValue>() {
+                let (key, value) = pair?;
+                let key = match key {
+                    LuaValue::String(s) => s.to_string_lossy().to_string(),
+                    key => key.to_string()?,
+                };
+                record_fields.push((key, field_value(lua, value)?));
+            }
+        }
+        // NOTE: Lua tables have no order, sorting fields
+        // by their keys keeps the output deterministic
+        record_fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(Self {
+            level,
+            task,
+            message: message.to_string_lossy().to_string(),
+            fields: record_fields,
+        })
+    }
+
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Human => self.format_human(),
+            LogFormat::Json => self.format_json(),
+        }
+    }
+
+    fn format_human(&self) -> String {
+        let mut line = format!(
+            "{} {} {} {}",
+            style(Local::now().format("%H:%M:%S%.3f"))
+                .for_stderr()
+                .dim(),
+            self.level.styled_label(),
+            style(format!("[task {}]", self.task)).for_stderr().dim(),
+            self.message
+        );
+        for (key, value) in &self.fields {
+            let value = match value {
+                JsonValue::String(s) if !needs_quotes(s) => s.clone(),
+                value => value.to_string(),
+            };
+            write!(
+                line,
+                " {}{value}",
+                style(format!("{key}=")).for_stderr().dim()
+            )
+            .expect("Writing to a string never fails");
+        }
+        line.push('\n');
+        line
+    }
+
+    fn format_json(&self) -> String {
+        let mut record = JsonMap::new();
+        record.insert(
+            "time".to_string(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .into(),
+        );
+        record.insert("level".to_string(), self.level.name().into());
+        record.insert("task".to_string(), self.task.into());
+        record.insert("message".to_string(), self.message.clone().into());
+        record.insert(
+            "fields".to_string(),
+            JsonValue::Object(self.fields.iter().cloned().collect()),
+        );
+        let mut line = JsonValue::Object(record).to_string();
+        line.push('\n');
+        line
+    }
+}
+
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty() || s.chars().any(|c| c.is_whitespace() || c == '"' || c == '=')
+}
+
+fn field_value(lua: &Lua, value: LuaValue) -> LuaResult<JsonValue> {
+    Ok(match value {
+        LuaValue::Nil => JsonValue::Null,
+        LuaValue::Boolean(b) => JsonValue::Bool(b),
+        LuaValue::Integer(i) => JsonValue::from(i),
+        LuaValue::Number(n) => match serde_json::Number::from_f64(n) {
+            Some(n) => JsonValue::Number(n),
+            None => JsonValue::String(n.to_string()),
+        },
+        LuaValue::String(s) => JsonValue::String(s.to_string_lossy().to_string()),
+        LuaValue::Table(t) => {
+            match lua.from_value_with(LuaValue::Table(t.clone()), LUA_DESERIALIZE_OPTIONS) {
+                Ok(value) => value,
+                Err(_) => JsonValue::String(LuaValue::Table(t).to_string()?),
+            }
+        }
+        value => JsonValue::String(value.to_string()?),
+    })
+}
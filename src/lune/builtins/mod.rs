@@ -4,6 +4,7 @@ use mlua::prelude::*;
 
 mod datetime;
 mod fs;
+mod log;
 mod luau;
 mod net;
 mod process;
@@ -14,10 +15,15 @@ mod task;
 #[cfg(feature = "roblox")]
 mod roblox;
 
+pub use net::{NetCassetteConfig, NetCassetteMode};
+pub use stdio::restore_terminal_mode;
+pub use task::{terminate_workers, WorkerPort};
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum LuneBuiltin {
     DateTime,
     Fs,
+    Log,
     Luau,
     Net,
     Task,
@@ -36,6 +42,7 @@ where
         match self {
             Self::DateTime => "datetime",
             Self::Fs => "fs",
+            Self::Log => "log",
             Self::Luau => "luau",
             Self::Net => "net",
             Self::Task => "task",
@@ -51,6 +58,7 @@ where
         let res = match self {
             Self::DateTime => datetime::create(lua),
             Self::Fs => fs::create(lua),
+            Self::Log => log::create(lua),
             Self::Luau => luau::create(lua),
             Self::Net => net::create(lua),
             Self::Task => task::create(lua),
@@ -76,6 +84,7 @@ impl FromStr for LuneBuiltin {
         match s.trim().to_ascii_lowercase().as_str() {
             "datetime" => Ok(Self::DateTime),
             "fs" => Ok(Self::Fs),
+            "log" => Ok(Self::Log),
             "luau" => Ok(Self::Luau),
             "net" => Ok(Self::Net),
             "task" => Ok(Self::Task),
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use mlua::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Mutex as AsyncMutex};
+
+const ENV_VAR_RECORD: &str = "LUNE_NET_RECORD";
+const ENV_VAR_REPLAY: &str = "LUNE_NET_REPLAY";
+
+/**
+    The mode to use for a network request cassette.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetCassetteMode {
+    /// Requests are sent as normal, and all request / response pairs are saved
+    Record,
+    /// Requests are never sent, responses are served from the cassette instead
+    Replay,
+}
+
+/**
+    Configuration for a network request cassette, stored in Lua app data.
+*/
+#[derive(Debug, Clone)]
+pub struct NetCassetteConfig {
+    pub mode: NetCassetteMode,
+    pub path: PathBuf,
+}
+
+impl NetCassetteConfig {
+    /**
+        Gets the cassette config to use for the given Lua struct.
+
+        A config stored in Lua app data takes precedence over any config given using
+        the `LUNE_NET_RECORD` and `LUNE_NET_REPLAY` environment variables.
+    */
+    pub fn get(lua: &Lua) -> LuaResult<Option<Self>> {
+        if let Some(config) = lua.app_data_ref::<Self>() {
+            return Ok(Some(config.clone()));
+        }
+        let record = env::var_os(ENV_VAR_RECORD).filter(|p| !p.is_empty());
+        let replay = env::var_os(ENV_VAR_REPLAY).filter(|p| !p.is_empty());
+        match (record, replay) {
+            (Some(_), Some(_)) => Err(LuaError::RuntimeError(format!(
+                "Only one of {ENV_VAR_RECORD} and {ENV_VAR_REPLAY} may be set"
+            ))),
+            (Some(path), None) => Ok(Some(Self {
+                mode: NetCassetteMode::Record,
+                path: PathBuf::from(path),
+            })),
+            (None, Some(path)) => Ok(Some(Self {
+                mode: NetCassetteMode::Replay,
+                path: PathBuf::from(path),
+            })),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NetCassetteBody {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+impl NetCassetteBody {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        // Keep bodies human-readable in the cassette file when
+        // possible, and only fall back to base64 for binary data
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Self {
+                body: Some(s.to_string()),
+                body_base64: None,
+            },
+            Err(_) => Self {
+                body: None,
+                body_base64: Some(BASE64.encode(bytes)),
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> LuaResult<Vec<u8>> {
+        match (&self.body, &self.body_base64) {
+            (_, Some(b64)) => BASE64.decode(b64).map_err(|e| {
+                LuaError::RuntimeError(format!("Invalid base64 body in cassette - {e}"))
+            }),
+            (Some(s), None) => Ok(s.as_bytes().to_vec()),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NetCassetteRequest {
+    method: String,
+    url: String,
+    #[serde(flatten)]
+    body: NetCassetteBody,
+}
+
+impl NetCassetteRequest {
+    fn from_request(request: &reqwest::Request) -> Self {
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .unwrap_or_default();
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            body: NetCassetteBody::from_bytes(body),
+        }
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        self.method == other.method
+            && self.url == other.url
+            && self.body.to_bytes().ok() == other.body.to_bytes().ok()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NetCassetteResponse {
+    status: u16,
+    headers: HashMap<String, String>,
+    #[serde(flatten)]
+    body: NetCassetteBody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NetCassetteEntry {
+    request: NetCassetteRequest,
+    response: NetCassetteResponse,
+    #[serde(skip)]
+    used: bool,
+}
+
+/**
+    A cassette of recorded network requests and their responses.
+
+    In record mode, the cassette file is rewritten after every request,
+    so that requests are not lost if the script exits unexpectedly.
+*/
+#[derive(Debug)]
+pub struct NetCassette {
+    mode: NetCassetteMode,
+    path: PathBuf,
+    entries: AsyncMutex<Vec<NetCassetteEntry>>,
+}
+
+impl NetCassette {
+    /**
+        Creates a new cassette from the given config.
+
+        In replay mode, this will read all entries from the cassette file right away.
+    */
+    pub fn new(config: NetCassetteConfig) -> LuaResult<Self> {
+        let entries = match config.mode {
+            NetCassetteMode::Record => Vec::new(),
+            NetCassetteMode::Replay => read_entries(&config.path)?,
+        };
+        Ok(Self {
+            mode: config.mode,
+            path: config.path,
+            entries: AsyncMutex::new(entries),
+        })
+    }
+
+    pub fn mode(&self) -> NetCassetteMode {
+        self.mode
+    }
+
+    /**
+        Records a request and its response, and saves the cassette file.
+    */
+    pub async fn record(
+        &self,
+        request: &reqwest::Request,
+        status: u16,
+        headers: &HashMap<String, String>,
+        body: &[u8],
+    ) -> LuaResult<()> {
+        let mut entries = self.entries.lock().await;
+        entries.push(NetCassetteEntry {
+            request: NetCassetteRequest::from_request(request),
+            response: NetCassetteResponse {
+                status,
+                headers: headers.clone(),
+                body: NetCassetteBody::from_bytes(body),
+            },
+            used: false,
+        });
+        let contents = serde_json::to_string_pretty(&*entries).into_lua_err()?;
+        fs::write(&self.path, contents).await.map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to write cassette file at '{}' - {e}",
+                self.path.display()
+            ))
+        })
+    }
+
+    /**
+        Finds a recorded response for the given request.
+
+        Identical requests are served in the order they were recorded in,
+        and once all of them have been served, the last one is reused.
+
+        Errors if no matching request was recorded.
+    */
+    pub async fn replay(
+        &self,
+        request: &reqwest::Request,
+    ) -> LuaResult<(u16, HashMap<String, String>, Vec<u8>)> {
+        let request = NetCassetteRequest::from_request(request);
+        let mut entries = self.entries.lock().await;
+
+        let mut matching = entries
+            .iter_mut()
+            .filter(|entry| entry.request.matches(&request))
+            .peekable();
+        if matching.peek().is_none() {
+            return Err(LuaError::RuntimeError(format!(
+                "No recorded response for request {} {} in cassette file at '{}'",
+                request.method,
+                request.url,
+                self.path.display()
+            )));
+        }
+
+        let mut last = None;
+        for entry in matching {
+            if !entry.used {
+                entry.used = true;
+                last = Some(entry);
+                break;
+            }
+            last = Some(entry);
+        }
+
+        let response = &last.expect("Matching entry should exist").response;
+        Ok((
+            response.status,
+            response.headers.clone(),
+            response.body.to_bytes()?,
+        ))
+    }
+}
+
+fn read_entries(path: &Path) -> LuaResult<Vec<NetCassetteEntry>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to read cassette file at '{}' - {e}",
+            path.display()
+        ))
+    })?;
+    serde_json::from_str(&contents).map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to parse cassette file at '{}' - {e}",
+            path.display()
+        ))
+    })
+}
@@ -1,20 +1,40 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use mlua::prelude::*;
 
 use hyper::{header::HeaderName, http::HeaderValue, HeaderMap};
-use reqwest::{IntoUrl, Method, RequestBuilder};
+use reqwest::{
+    tls::{Certificate, Identity},
+    IntoUrl, Method, RequestBuilder,
+};
+use tokio::{fs, sync::Mutex as AsyncMutex};
+
+use crate::lune::util::{formatting::format_label, progress::write_stderr};
+
+use super::{
+    cassette::{NetCassette, NetCassetteConfig, NetCassetteMode},
+    config::RequestConfigTls,
+};
 
 const REGISTRY_KEY: &str = "NetClient";
 
+const INSECURE_TLS_WARNING: &str = "\
+TLS certificate verification has been disabled for net.request!
+This is insecure, and must only be used for local testing against
+self-signed certificates, never for any real network traffic.";
+
 pub struct NetClientBuilder {
     builder: reqwest::ClientBuilder,
+    headers: HeaderMap,
+    cassette: Option<NetCassetteConfig>,
 }
 
 impl NetClientBuilder {
     pub fn new() -> NetClientBuilder {
         Self {
             builder: reqwest::ClientBuilder::new(),
+            headers: HeaderMap::new(),
+            cassette: None,
         }
     }
 
@@ -29,22 +49,191 @@ impl NetClientBuilder {
             let hval = HeaderValue::from_bytes(val.as_ref()).into_lua_err()?;
             map.insert(hkey, hval);
         }
-        self.builder = self.builder.default_headers(map);
+        self.headers = map;
+        Ok(self)
+    }
+
+    pub fn cassette(mut self, config: Option<NetCassetteConfig>) -> Self {
+        self.cassette = config;
+        self
+    }
+
+    /**
+        Applies the given tls options to the client being built,
+        reading any certificate and key files that they point to.
+    */
+    pub async fn tls(mut self, tls: &RequestConfigTls) -> LuaResult<Self> {
+        for path in &tls.ca {
+            let pem = read_pem_file(path, "certificate authority").await?;
+            let certs = rustls_pemfile::certs(&mut pem.as_slice()).map_err(|e| {
+                LuaError::RuntimeError(format!(
+                    "Failed to parse certificate authority file at '{}' - {e}",
+                    path.display()
+                ))
+            })?;
+            if certs.is_empty() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Certificate authority file at '{}' contains no certificates",
+                    path.display()
+                )));
+            }
+            for cert in certs {
+                let cert = Certificate::from_der(&cert).into_lua_err()?;
+                self.builder = self.builder.add_root_certificate(cert);
+            }
+        }
+        if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+            // NOTE: The identity for rustls must be a single buffer
+            // that contains both the private key and certificate(s)
+            let mut pem = read_pem_file(key, "client key").await?;
+            pem.push(b'\n');
+            pem.extend(read_pem_file(cert, "client certificate").await?);
+            let identity = Identity::from_pem(&pem).map_err(|e| {
+                LuaError::RuntimeError(format!("Failed to parse client certificate or key - {e}"))
+            })?;
+            self.builder = self.builder.identity(identity);
+        }
+        if let Some(version) = tls.min_version {
+            self.builder = self.builder.min_tls_version(version.as_tls_version());
+        }
+        if tls.insecure {
+            self.builder = self.builder.danger_accept_invalid_certs(true);
+        }
         Ok(self)
     }
 
     pub fn build(self) -> LuaResult<NetClient> {
-        let client = self.builder.build().into_lua_err()?;
-        Ok(NetClient(client))
+        let client = self
+            .builder
+            .default_headers(self.headers.clone())
+            .build()
+            .into_lua_err()?;
+        let cassette = self.cassette.map(NetCassette::new).transpose()?;
+        Ok(NetClient {
+            inner: client,
+            headers: self.headers,
+            cassette: cassette.map(Arc::new),
+            tls_clients: Arc::new(AsyncMutex::new(HashMap::new())),
+        })
     }
 }
 
+async fn read_pem_file(path: &std::path::Path, kind: &str) -> LuaResult<Vec<u8>> {
+    fs::read(path).await.map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to read {kind} file at '{}' - {e}",
+            path.display()
+        ))
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct NetClientResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
 #[derive(Debug, Clone)]
-pub struct NetClient(reqwest::Client);
+pub struct NetClient {
+    inner: reqwest::Client,
+    headers: HeaderMap,
+    cassette: Option<Arc<NetCassette>>,
+    tls_clients: Arc<AsyncMutex<HashMap<RequestConfigTls, reqwest::Client>>>,
+}
 
 impl NetClient {
     pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
-        self.0.request(method, url)
+        self.inner.request(method, url)
+    }
+
+    /**
+        Gets a client that uses the given tls options, and otherwise
+        shares default headers and any cassette with this client.
+
+        Clients are cached for each distinct set of tls options, so
+        certificate files are only read once and connections get reused.
+
+        A warning is emitted when a client that has certificate verification
+        disabled is first created, once for each distinct set of tls options.
+    */
+    pub async fn with_tls(&self, tls: &RequestConfigTls) -> LuaResult<Self> {
+        let mut tls_clients = self.tls_clients.lock().await;
+        let inner = match tls_clients.get(tls) {
+            Some(inner) => inner.clone(),
+            None => {
+                let mut builder = NetClientBuilder::new().tls(tls).await?;
+                builder.headers = self.headers.clone();
+                let inner = builder.build()?.inner;
+                if tls.insecure {
+                    let warning = format!("{}\n{INSECURE_TLS_WARNING}\n", format_label("warn"));
+                    write_stderr(warning.as_bytes()).await?;
+                }
+                tls_clients.insert(tls.clone(), inner.clone());
+                inner
+            }
+        };
+        Ok(Self {
+            inner,
+            ..self.clone()
+        })
+    }
+
+    /**
+        Sends the given request and reads the full response.
+
+        If a cassette is being used, this will either record the request
+        and its response, or serve the response from the cassette instead.
+    */
+    pub async fn send(&self, request: RequestBuilder) -> LuaResult<NetClientResponse> {
+        let request = request.build().into_lua_err()?;
+
+        if let Some(cassette) = &self.cassette {
+            if cassette.mode() == NetCassetteMode::Replay {
+                let (status, headers, body) = cassette.replay(&request).await?;
+                return Ok(NetClientResponse {
+                    status,
+                    headers,
+                    body,
+                });
+            }
+        }
+
+        // NOTE: Requests with streaming bodies can not be cloned, and sending them
+        // without recording them would only make replaying fail later on instead
+        let recorded_request = match &self.cassette {
+            Some(_) => Some(request.try_clone().ok_or_else(|| {
+                LuaError::RuntimeError(format!(
+                    "Failed to record request to '{}' - requests with streaming bodies can not be recorded",
+                    request.url()
+                ))
+            })?),
+            None => None,
+        };
+
+        let res = self.inner.execute(request).await.into_lua_err()?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    value.to_str().unwrap().to_owned(),
+                )
+            })
+            .collect::<HashMap<String, String>>();
+        let body = res.bytes().await.into_lua_err()?.to_vec();
+
+        if let (Some(cassette), Some(request)) = (&self.cassette, recorded_request) {
+            cassette.record(&request, status, &headers, &body).await?;
+        }
+
+        Ok(NetClientResponse {
+            status,
+            headers,
+            body,
+        })
     }
 
     pub fn into_registry(self, lua: &Lua) {
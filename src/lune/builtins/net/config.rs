@@ -1,24 +1,178 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, path::PathBuf};
 
 use mlua::prelude::*;
 
-use reqwest::Method;
+use reqwest::{tls::Version as TlsVersion, Method};
+use url::Url;
+
+use super::url::{append_url_query, parse_url};
 
 // Net request config
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum RequestConfigTlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+    Tls13,
+}
+
+impl RequestConfigTlsVersion {
+    pub fn all() -> &'static [Self] {
+        &[Self::Tls10, Self::Tls11, Self::Tls12, Self::Tls13]
+    }
+
+    pub fn as_tls_version(self) -> TlsVersion {
+        match self {
+            Self::Tls10 => TlsVersion::TLS_1_0,
+            Self::Tls11 => TlsVersion::TLS_1_1,
+            Self::Tls12 => TlsVersion::TLS_1_2,
+            Self::Tls13 => TlsVersion::TLS_1_3,
+        }
+    }
+}
+
+impl fmt::Display for RequestConfigTlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match *self {
+            Self::Tls10 => "1.0",
+            Self::Tls11 => "1.1",
+            Self::Tls12 => "1.2",
+            Self::Tls13 => "1.3",
+        };
+        f.write_str(s)
+    }
+}
+
+impl<'lua> FromLua<'lua> for RequestConfigTlsVersion {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let s = match &value {
+            LuaValue::String(s) => s.to_str()?.trim().to_string(),
+            LuaValue::Number(n) => format!("{n:.1}"),
+            LuaValue::Integer(i) => format!("{i}.0"),
+            _ => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'minVersion' in request tls options - expected string, got '{}'",
+                    value.type_name()
+                )))
+            }
+        };
+        Self::all()
+            .iter()
+            .find(|v| v.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                LuaError::RuntimeError(format!(
+                    "Invalid option 'minVersion' in request tls options - got '{s}', expected one of {}",
+                    Self::all()
+                        .iter()
+                        .map(|v| format!("'{v}'"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub struct RequestConfigTls {
+    pub ca: Vec<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub min_version: Option<RequestConfigTlsVersion>,
+    pub insecure: bool,
+}
+
+impl<'lua> FromLua<'lua> for RequestConfigTls {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        let tab = match value {
+            LuaValue::Table(tab) => tab,
+            value => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "RequestConfigTls",
+                    message: Some(format!(
+                        "Invalid request tls options - expected table, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        // Extract paths to certificate authority bundles, one or many
+        let ca = match tab.raw_get::<_, LuaValue>("ca")? {
+            LuaValue::Nil => Vec::new(),
+            LuaValue::String(s) => vec![PathBuf::from(s.to_str()?)],
+            LuaValue::Table(t) => t
+                .sequence_values::<String>()
+                .map(|p| p.map(PathBuf::from))
+                .collect::<LuaResult<_>>()?,
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'ca' in request tls options - expected string or table, got '{}'",
+                    value.type_name()
+                )))
+            }
+        };
+        // Extract client certificate & key, these must be given together
+        let cert = tab
+            .raw_get::<_, Option<String>>("cert")
+            .map_err(|_| {
+                LuaError::runtime("Invalid option value for 'cert' in request tls options")
+            })?
+            .map(PathBuf::from);
+        let key = tab
+            .raw_get::<_, Option<String>>("key")
+            .map_err(|_| {
+                LuaError::runtime("Invalid option value for 'key' in request tls options")
+            })?
+            .map(PathBuf::from);
+        if cert.is_some() != key.is_some() {
+            return Err(LuaError::runtime(
+                "Options 'cert' and 'key' in request tls options must be given together",
+            ));
+        }
+        // Extract minimum tls version & verification flag
+        let min_version = match tab.raw_get::<_, LuaValue>("minVersion")? {
+            LuaValue::Nil => None,
+            value => Some(RequestConfigTlsVersion::from_lua(value, lua)?),
+        };
+        let insecure = match tab.raw_get::<_, LuaValue>("insecure")? {
+            LuaValue::Nil => false,
+            LuaValue::Boolean(b) => b,
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'insecure' in request tls options - expected boolean, got '{}'",
+                    value.type_name()
+                )))
+            }
+        };
+        Ok(Self {
+            ca,
+            cert,
+            key,
+            min_version,
+            insecure,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RequestConfigOptions {
     pub decompress: bool,
+    pub tls: Option<RequestConfigTls>,
 }
 
 impl Default for RequestConfigOptions {
     fn default() -> Self {
-        Self { decompress: true }
+        Self {
+            decompress: true,
+            tls: None,
+        }
     }
 }
 
 impl<'lua> FromLua<'lua> for RequestConfigOptions {
-    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
         // Nil means default options, table means custom options
         if let LuaValue::Nil = value {
             return Ok(Self::default());
@@ -30,7 +184,12 @@ impl<'lua> FromLua<'lua> for RequestConfigOptions {
                     "Invalid option value for 'decompress' in request config options".to_string(),
                 )),
             }?;
-            return Ok(Self { decompress });
+            // Extract tls options
+            let tls = match tab.raw_get::<_, LuaValue>("tls")? {
+                LuaValue::Nil => None,
+                value => Some(RequestConfigTls::from_lua(value, lua)?),
+            };
+            return Ok(Self { decompress, tls });
         }
         // Anything else is invalid
         Err(LuaError::FromLuaConversionError {
@@ -152,6 +311,22 @@ impl<'lua> FromLua<'lua> for RequestConfig<'lua> {
     }
 }
 
+impl RequestConfig<'_> {
+    /**
+        Parses the url for this request config, and merges
+        any query parameters from the config into it.
+    */
+    pub fn url_with_query(&self) -> LuaResult<Url> {
+        let mut url = parse_url(&self.url, None)?;
+        let mut query = Vec::with_capacity(self.query.len());
+        for (key, value) in &self.query {
+            query.push((key.to_str()?, value.to_str()?));
+        }
+        append_url_query(&mut url, query);
+        Ok(url)
+    }
+}
+
 // Net serve config
 
 pub struct ServeConfig<'a> {
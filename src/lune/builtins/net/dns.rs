@@ -0,0 +1,41 @@
+use std::net::IpAddr;
+
+use mlua::prelude::*;
+
+use tokio::{net::lookup_host, task};
+
+/**
+    Resolves all of the IPv4 and IPv6 addresses for the given host.
+
+    This uses the system resolver, meaning that the hosts
+    file is respected and localhost resolves even when offline.
+*/
+pub async fn lookup_addresses(host: &str) -> LuaResult<Vec<IpAddr>> {
+    // NOTE: The system resolver needs a port to resolve socket
+    // addresses, but the port is not relevant to us so we use 0
+    let addrs = lookup_host((host, 0))
+        .await
+        .map_err(|e| LuaError::RuntimeError(format!("Failed to look up host '{host}' - {e}")))?;
+
+    // The resolver may give back the same address several
+    // times, once for each socket type, so we deduplicate
+    let mut ips = Vec::new();
+    for addr in addrs {
+        let ip = addr.ip();
+        if !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+
+    Ok(ips)
+}
+
+/**
+    Resolves the host name for the given address, using the system resolver.
+*/
+pub async fn lookup_host_name(addr: IpAddr) -> LuaResult<String> {
+    task::spawn_blocking(move || dns_lookup::lookup_addr(&addr))
+        .await
+        .into_lua_err()?
+        .map_err(|e| LuaError::RuntimeError(format!("Failed to look up address '{addr}' - {e}")))
+}
@@ -0,0 +1,61 @@
+use std::net::IpAddr;
+
+use if_addrs::{get_if_addrs, IfAddr};
+use mlua::prelude::*;
+
+use crate::lune::util::TableBuilder;
+
+/**
+    A single address for a local network interface.
+
+    Interfaces with several addresses, such as both an
+    IPv4 and an IPv6 address, will have one entry each.
+*/
+#[derive(Debug, Clone)]
+pub struct NetInterface {
+    name: String,
+    address: IpAddr,
+    netmask: IpAddr,
+    loopback: bool,
+}
+
+impl NetInterface {
+    pub fn get_all() -> LuaResult<Vec<Self>> {
+        let interfaces = get_if_addrs().map_err(|e| {
+            LuaError::RuntimeError(format!("Failed to get network interfaces - {e}"))
+        })?;
+        Ok(interfaces
+            .into_iter()
+            .map(|interface| {
+                let loopback = interface.is_loopback();
+                let (address, netmask) = match interface.addr {
+                    IfAddr::V4(v4) => (IpAddr::V4(v4.ip), IpAddr::V4(v4.netmask)),
+                    IfAddr::V6(v6) => (IpAddr::V6(v6.ip), IpAddr::V6(v6.netmask)),
+                };
+                Self {
+                    name: interface.name,
+                    address,
+                    netmask,
+                    loopback,
+                }
+            })
+            .collect())
+    }
+}
+
+impl<'lua> IntoLua<'lua> for NetInterface {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let family = match self.address {
+            IpAddr::V4(_) => "ipv4",
+            IpAddr::V6(_) => "ipv6",
+        };
+        TableBuilder::new(lua)?
+            .with_value("name", self.name)?
+            .with_value("family", family)?
+            .with_value("address", self.address.to_string())?
+            .with_value("netmask", self.netmask.to_string())?
+            .with_value("loopback", self.loopback)?
+            .build_readonly()?
+            .into_lua(lua)
+    }
+}
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::net::IpAddr;
 
 use mlua::prelude::*;
 
-use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+use hyper::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH},
+    StatusCode,
+};
 
 use crate::lune::{scheduler::Scheduler, util::TableBuilder};
 
@@ -13,21 +16,28 @@ use super::serde::{
     encode_decode::{EncodeDecodeConfig, EncodeDecodeFormat},
 };
 
+mod cassette;
 mod client;
 mod config;
+mod dns;
+mod interfaces;
 mod processing;
 mod response;
 mod server;
+mod url;
 mod websocket;
 
+pub use cassette::{NetCassetteConfig, NetCassetteMode};
 use client::{NetClient, NetClientBuilder};
 use config::{RequestConfig, ServeConfig};
+use interfaces::NetInterface;
 use server::bind_to_localhost;
 use websocket::NetWebSocket;
 
 pub fn create(lua: &'static Lua) -> LuaResult<LuaTable> {
     NetClientBuilder::new()
         .headers(&[("User-Agent", create_user_agent_header())])?
+        .cassette(NetCassetteConfig::get(lua)?)
         .build()?
         .into_registry(lua);
     TableBuilder::new(lua)?
@@ -36,8 +46,12 @@ pub fn create(lua: &'static Lua) -> LuaResult<LuaTable> {
         .with_async_function("request", net_request)?
         .with_async_function("socket", net_socket)?
         .with_async_function("serve", net_serve)?
+        .with_async_function("lookup", net_lookup)?
+        .with_async_function("reverseLookup", net_reverse_lookup)?
+        .with_function("interfaces", net_interfaces)?
         .with_function("urlEncode", net_url_encode)?
         .with_function("urlDecode", net_url_decode)?
+        .with_value("url", url::create(lua)?)?
         .build_readonly()
 }
 
@@ -66,38 +80,29 @@ where
     'lua: 'static, // FIXME: Get rid of static lifetime bound here
 {
     // Create and send the request
-    let client = NetClient::from_registry(lua);
-    let mut request = client.request(config.method, &config.url);
-    for (query, value) in config.query {
-        request = request.query(&[(query.to_str()?, value.to_str()?)]);
+    let mut client = NetClient::from_registry(lua);
+    if let Some(tls) = &config.options.tls {
+        client = client.with_tls(tls).await?;
     }
+    let mut request = client.request(config.method.clone(), config.url_with_query()?);
     for (header, value) in config.headers {
         request = request.header(header.to_str()?, value.to_str()?);
     }
-    let res = request
-        .body(config.body.unwrap_or_default())
-        .send()
-        .await
-        .into_lua_err()?;
-    // Extract status, headers
-    let res_status = res.status().as_u16();
-    let res_status_text = res.status().canonical_reason();
-    let mut res_headers = res
-        .headers()
-        .iter()
-        .map(|(name, value)| {
-            (
-                name.as_str().to_string(),
-                value.to_str().unwrap().to_owned(),
-            )
-        })
-        .collect::<HashMap<String, String>>();
-    // Read response bytes
-    let mut res_bytes = res.bytes().await.into_lua_err()?.to_vec();
+    let res = client
+        .send(request.body(config.body.unwrap_or_default()))
+        .await?;
+    // Extract status, headers, body
+    let res_status = res.status;
+    let res_status_text = StatusCode::from_u16(res_status)
+        .ok()
+        .and_then(|s| s.canonical_reason());
+    let mut res_headers = res.headers;
+    let mut res_bytes = res.body;
     // Check for extra options, decompression
     if config.options.decompress {
-        // NOTE: Header names are guaranteed to be lowercase because of the above
-        // transformations of them into the hashmap, so we can compare directly
+        // NOTE: Header names are guaranteed to be lowercase because of the
+        // transformations of them into the hashmap in our client, so we can
+        // compare directly
         let format = res_headers.iter().find_map(|(name, val)| {
             if name == CONTENT_ENCODING.as_str() {
                 CompressDecompressFormat::detect_from_header_str(val)
@@ -148,6 +153,24 @@ where
     create_server(lua, &sched, config, builder)
 }
 
+async fn net_lookup(_: &Lua, host: String) -> LuaResult<Vec<String>> {
+    let addrs = dns::lookup_addresses(&host).await?;
+    Ok(addrs.iter().map(ToString::to_string).collect())
+}
+
+async fn net_reverse_lookup(_: &Lua, address: String) -> LuaResult<String> {
+    let addr = address.parse::<IpAddr>().map_err(|_| {
+        LuaError::RuntimeError(format!(
+            "Invalid address '{address}' - expected an IP address"
+        ))
+    })?;
+    dns::lookup_host_name(addr).await
+}
+
+fn net_interfaces(_: &Lua, _: ()) -> LuaResult<Vec<NetInterface>> {
+    NetInterface::get_all()
+}
+
 fn net_url_encode<'lua>(
     lua: &'lua Lua,
     (lua_string, as_binary): (LuaString<'lua>, Option<bool>),
@@ -89,14 +89,17 @@ where
                 } else {
                     let processed = ProcessedRequest::from_request(req).await?;
                     let request_id = processed.id;
-                    if (tx_request.send(processed).await).is_err() {
-                        return Err(LuaError::runtime("Lua handler is busy"));
-                    }
+                    // NOTE: The response sender must be inserted before the request
+                    // is sent to lua, otherwise a fast handler may finish first
                     let (response_tx, response_rx) = oneshot::channel::<NetServeResponse>();
                     response_senders
                         .lock()
                         .await
                         .insert(request_id, response_tx);
+                    if (tx_request.send(processed).await).is_err() {
+                        response_senders.lock().await.remove(&request_id);
+                        return Err(LuaError::runtime("Lua handler is busy"));
+                    }
                     match response_rx.await {
                         Err(_) => Err(LuaError::runtime("Internal Server Error")),
                         Ok(r) => r.into_response(),
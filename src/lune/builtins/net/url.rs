@@ -0,0 +1,143 @@
+use mlua::prelude::*;
+
+use url::Url;
+
+use crate::lune::util::TableBuilder;
+
+pub fn create(lua: &'static Lua) -> LuaResult<LuaTable<'_>> {
+    TableBuilder::new(lua)?
+        .with_function("parse", net_url_parse)?
+        .with_function("build", net_url_build)?
+        .with_function("resolve", net_url_resolve)?
+        .build_readonly()
+}
+
+/**
+    Parses the given url string, optionally relative to a base url.
+
+    Parsing follows the [WHATWG URL Standard](https://url.spec.whatwg.org/),
+    meaning that urls are normalized in the same way that browsers do it.
+*/
+pub fn parse_url(url: &str, base: Option<&str>) -> LuaResult<Url> {
+    let res = match base {
+        None => Url::parse(url),
+        Some(base) => Url::parse(base).and_then(|base| base.join(url)),
+    };
+    res.map_err(|e| LuaError::RuntimeError(format!("Failed to parse url '{url}' - {e}")))
+}
+
+/**
+    Appends the given query pairs to the url, keeping any that already exist.
+*/
+pub fn append_url_query<K, V>(url: &mut Url, pairs: impl IntoIterator<Item = (K, V)>)
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut pairs = pairs.into_iter().peekable();
+    if pairs.peek().is_some() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+}
+
+fn url_into_lua_table(lua: &Lua, url: Url) -> LuaResult<LuaTable<'_>> {
+    // FUTURE: Make inner tables for query keys that have multiple values?
+    let query = lua.create_table()?;
+    for (key, value) in url.query_pairs() {
+        query.set(key.as_ref(), value.as_ref())?;
+    }
+
+    let username = match url.username() {
+        "" => None,
+        u => Some(u),
+    };
+
+    // NOTE: Ports that are the default for the scheme are never stored in the url,
+    // so we strip any explicit port from a copy of it to get the default port
+    let default_port = {
+        let mut url = url.clone();
+        url.set_port(None).ok();
+        url.port_or_known_default()
+    };
+
+    TableBuilder::new(lua)?
+        .with_value("href", url.as_str())?
+        .with_value("scheme", url.scheme())?
+        .with_value("username", username)?
+        .with_value("password", url.password())?
+        .with_value("host", url.host_str())?
+        .with_value("port", url.port())?
+        .with_value("defaultPort", default_port)?
+        .with_value("path", url.path())?
+        .with_value("query", query)?
+        .with_value("fragment", url.fragment())?
+        .build()
+}
+
+fn url_from_lua_table(parts: LuaTable) -> LuaResult<Url> {
+    let scheme = parts
+        .get::<_, Option<String>>("scheme")?
+        .ok_or_else(|| LuaError::runtime("Missing 'scheme' in url parts"))?;
+    let host = parts.get::<_, Option<String>>("host")?;
+
+    /*
+        We start out with a minimal url for the given scheme and
+        then set each part separately, this lets the url crate
+        handle validation and percent-encoding for every part
+    */
+    let base = match &host {
+        Some(host) => format!("{scheme}://{host}"),
+        None => format!("{scheme}:"),
+    };
+    let mut url = Url::parse(&base)
+        .map_err(|e| LuaError::RuntimeError(format!("Invalid url scheme or host - {e}")))?;
+
+    if let Some(username) = parts.get::<_, Option<String>>("username")? {
+        url.set_username(&username)
+            .map_err(|_| LuaError::runtime("Invalid url parts - cannot set 'username'"))?;
+    }
+    if let Some(password) = parts.get::<_, Option<String>>("password")? {
+        url.set_password(Some(&password))
+            .map_err(|_| LuaError::runtime("Invalid url parts - cannot set 'password'"))?;
+    }
+    if let Some(port) = parts.get::<_, Option<u16>>("port")? {
+        url.set_port(Some(port))
+            .map_err(|_| LuaError::runtime("Invalid url parts - cannot set 'port'"))?;
+    }
+    if let Some(path) = parts.get::<_, Option<String>>("path")? {
+        url.set_path(&path);
+    }
+    if let Some(query) = parts.get::<_, Option<LuaTable>>("query")? {
+        let mut pairs = Vec::new();
+        for pair in query.pairs::<String, String>() {
+            pairs.push(pair?);
+        }
+        // NOTE: Table iteration order is not stable, so we sort
+        // the query to make sure built urls are deterministic
+        pairs.sort();
+        append_url_query(&mut url, pairs);
+    }
+    if let Some(fragment) = parts.get::<_, Option<String>>("fragment")? {
+        url.set_fragment(Some(&fragment));
+    }
+
+    Ok(url)
+}
+
+fn net_url_parse<'lua>(
+    lua: &'lua Lua,
+    (url, base): (String, Option<String>),
+) -> LuaResult<LuaTable<'lua>> {
+    let url = parse_url(&url, base.as_deref())?;
+    url_into_lua_table(lua, url)
+}
+
+fn net_url_build(_: &Lua, parts: LuaTable) -> LuaResult<String> {
+    let url = url_from_lua_table(parts)?;
+    Ok(url.into())
+}
+
+fn net_url_resolve(_: &Lua, (base, url): (String, String)) -> LuaResult<String> {
+    let url = parse_url(&url, Some(&base))?;
+    Ok(url.into())
+}
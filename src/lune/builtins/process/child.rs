@@ -0,0 +1,338 @@
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+};
+
+use mlua::prelude::*;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    process::Child,
+    sync::{mpsc, oneshot, watch, Mutex as AsyncMutex},
+    task,
+};
+
+use crate::lune::util::TableBuilder;
+
+use super::{
+    options::{ProcessSpawnOptions, ProcessSpawnOptionsPty, ProcessSpawnOptionsStdioKind},
+    pty::{ProcessPty, ProcessPtyExt},
+    signal::{exit_code_from_status, ProcessSignal},
+};
+
+type ChildReadStream = BufReader<ChildReadBox>;
+type ChildReadBox = Box<dyn AsyncRead + Send + Unpin>;
+type ChildWriteStream = Box<dyn AsyncWrite + Send + Unpin>;
+type ChildKillRequest = (ProcessSignal, oneshot::Sender<io::Result<()>>);
+
+/**
+    A readable output stream of a child process.
+
+    Streams that were not piped, such as when stdio is set to
+    `"forward"` or `"none"`, behave as if they were already closed.
+*/
+#[derive(Clone)]
+struct ChildReader {
+    inner: Option<Arc<AsyncMutex<ChildReadStream>>>,
+}
+
+impl ChildReader {
+    fn new(stream: Option<ChildReadBox>) -> Self {
+        Self {
+            inner: stream.map(|s| Arc::new(AsyncMutex::new(BufReader::new(s)))),
+        }
+    }
+
+    async fn read(&self, max_bytes: Option<usize>) -> LuaResult<Option<Vec<u8>>> {
+        let Some(inner) = &self.inner else {
+            return Ok(None);
+        };
+        let mut reader = inner.lock().await;
+        let buf = reader.fill_buf().await.into_lua_err()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        let len = max_bytes.unwrap_or(buf.len()).min(buf.len());
+        let bytes = buf[..len].to_vec();
+        reader.consume(len);
+        Ok(Some(bytes))
+    }
+
+    async fn read_line(&self) -> LuaResult<Option<Vec<u8>>> {
+        let Some(inner) = &self.inner else {
+            return Ok(None);
+        };
+        let mut line = Vec::new();
+        let mut reader = inner.lock().await;
+        if reader.read_until(b'\n', &mut line).await.into_lua_err()? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    async fn read_to_end(&self) -> LuaResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        if let Some(inner) = &self.inner {
+            let mut reader = inner.lock().await;
+            reader.read_to_end(&mut bytes).await.into_lua_err()?;
+        }
+        Ok(bytes)
+    }
+
+    fn into_lua_table(self, lua: &'static Lua) -> LuaResult<LuaTable<'static>> {
+        let (this_read, this_line, this_end) = (self.clone(), self.clone(), self);
+        TableBuilder::new(lua)?
+            .with_async_function("read", move |lua, max_bytes: Option<usize>| {
+                let this = this_read.clone();
+                async move {
+                    match this.read(max_bytes).await? {
+                        Some(bytes) => Ok(Some(lua.create_string(bytes)?)),
+                        None => Ok(None),
+                    }
+                }
+            })?
+            .with_async_function("readLine", move |lua, ()| {
+                let this = this_line.clone();
+                async move {
+                    match this.read_line().await? {
+                        Some(bytes) => Ok(Some(lua.create_string(bytes)?)),
+                        None => Ok(None),
+                    }
+                }
+            })?
+            .with_async_function("readToEnd", move |lua, ()| {
+                let this = this_end.clone();
+                async move { lua.create_string(this.read_to_end().await?) }
+            })?
+            .build_readonly()
+    }
+}
+
+/**
+    The writable input stream of a child process.
+*/
+#[derive(Clone)]
+struct ChildWriter {
+    inner: Arc<AsyncMutex<Option<ChildWriteStream>>>,
+}
+
+impl ChildWriter {
+    fn new(stdin: Option<ChildWriteStream>) -> Self {
+        Self {
+            inner: Arc::new(AsyncMutex::new(stdin)),
+        }
+    }
+
+    async fn write(&self, bytes: &[u8]) -> LuaResult<()> {
+        let mut inner = self.inner.lock().await;
+        let stdin = inner
+            .as_mut()
+            .ok_or_else(|| LuaError::runtime("Stdin of child process has been closed"))?;
+        stdin.write_all(bytes).await.into_lua_err()?;
+        stdin.flush().await.into_lua_err()
+    }
+
+    async fn close(&self) -> LuaResult<()> {
+        if let Some(mut stdin) = self.inner.lock().await.take() {
+            stdin.shutdown().await.into_lua_err()?;
+        }
+        Ok(())
+    }
+
+    fn into_lua_table(self, lua: &'static Lua) -> LuaResult<LuaTable<'static>> {
+        let (this_write, this_close) = (self.clone(), self);
+        TableBuilder::new(lua)?
+            .with_async_function("write", move |_, data: LuaString| {
+                let this = this_write.clone();
+                let bytes = data.as_bytes().to_vec();
+                async move { this.write(&bytes).await }
+            })?
+            .with_async_function("close", move |_, ()| {
+                let this = this_close.clone();
+                async move { this.close().await }
+            })?
+            .build_readonly()
+    }
+}
+
+/**
+    A handle to a running child process, created using `process.create`.
+
+    The child process itself is owned by a background task that waits for it
+    to exit, signals are sent to the child through that same task to make
+    sure we never signal a process id that has already been reaped.
+
+    When the child process is attached to a pseudo-terminal, its stdin and stdout
+    are both the terminal, and stderr is merged into stdout by the terminal itself.
+*/
+pub(super) struct ProcessChild {
+    pid: Option<u32>,
+    stdin: ChildWriter,
+    stdout: ChildReader,
+    stderr: ChildReader,
+    pty: Option<ProcessPty>,
+    kill_tx: mpsc::UnboundedSender<ChildKillRequest>,
+    status_rx: watch::Receiver<Option<ExitStatus>>,
+}
+
+impl ProcessChild {
+    pub async fn spawn(
+        program: String,
+        args: Option<Vec<String>>,
+        mut options: ProcessSpawnOptions,
+    ) -> LuaResult<Self> {
+        let stdout = options.stdio.stdout;
+        let stderr = options.stdio.stderr;
+        let stdin = options.stdio.stdin.take();
+        let pty_size = options.pty.take();
+
+        let mut cmd = options.into_command(program, args);
+        let pty = match pty_size {
+            Some(size) => Some(ProcessPty::attach(&mut cmd, size)?),
+            None => {
+                cmd.stdin(Stdio::piped())
+                    .stdout(as_child_stdio(stdout))
+                    .stderr(as_child_stdio(stderr));
+                None
+            }
+        };
+
+        // NOTE: The command holds onto our copies of the slave side of the pty,
+        // those must be closed for reads to end once the child process exits
+        let mut child = cmd.spawn()?;
+        drop(cmd);
+
+        let (mut child_stdin, child_stdout, child_stderr): (
+            Option<ChildWriteStream>,
+            Option<ChildReadBox>,
+            Option<ChildReadBox>,
+        ) = match &pty {
+            Some(pty) => (Some(pty.writer()), Some(pty.reader()), None),
+            None => (
+                child.stdin.take().map(|s| Box::new(s) as _),
+                child.stdout.take().map(|s| Box::new(s) as _),
+                child.stderr.take().map(|s| Box::new(s) as _),
+            ),
+        };
+        if let (Some(child_stdin), Some(stdin)) = (child_stdin.as_mut(), stdin) {
+            child_stdin.write_all(&stdin).await.into_lua_err()?;
+        }
+
+        let (kill_tx, kill_rx) = mpsc::unbounded_channel();
+        let (status_tx, status_rx) = watch::channel(None);
+
+        let this = Self {
+            pid: child.id(),
+            stdin: ChildWriter::new(child_stdin),
+            stdout: ChildReader::new(child_stdout),
+            stderr: ChildReader::new(child_stderr),
+            pty,
+            kill_tx,
+            status_rx,
+        };
+
+        task::spawn(drive_child(child, kill_rx, status_tx));
+
+        Ok(this)
+    }
+
+    pub fn into_lua_table(self, lua: &'static Lua) -> LuaResult<LuaTable<'static>> {
+        let kill_tx = self.kill_tx;
+        let wait_rx = self.status_rx.clone();
+        let status_rx = self.status_rx;
+        let pty = self.pty;
+        TableBuilder::new(lua)?
+            .with_value("pid", self.pid)?
+            .with_value("stdin", self.stdin.into_lua_table(lua)?)?
+            .with_value("stdout", self.stdout.into_lua_table(lua)?)?
+            .with_value("stderr", self.stderr.into_lua_table(lua)?)?
+            .with_async_function("kill", move |_, signal: ProcessSignal| {
+                let kill_tx = kill_tx.clone();
+                async move {
+                    let (res_tx, res_rx) = oneshot::channel();
+                    if kill_tx.send((signal, res_tx)).is_err() {
+                        return Ok(false);
+                    }
+                    match res_rx.await {
+                        Ok(Ok(_)) => Ok(true),
+                        Ok(Err(e)) => Err(LuaError::RuntimeError(format!(
+                            "Failed to send {signal} to child process - {e}"
+                        ))),
+                        // The child process exited before our signal could be sent
+                        Err(_) => Ok(false),
+                    }
+                }
+            })?
+            .with_function("resize", move |_, (cols, rows): (u16, u16)| match &pty {
+                Some(pty) => pty.resize(ProcessSpawnOptionsPty { cols, rows }),
+                None => Err(LuaError::runtime(
+                    "Child process is not attached to a pty - use the 'pty' option to create one",
+                )),
+            })?
+            .with_async_function("wait", move |lua, ()| {
+                let mut status_rx = wait_rx.clone();
+                async move {
+                    let status = status_rx
+                        .wait_for(Option::is_some)
+                        .await
+                        .map_err(|_| LuaError::runtime("Failed to wait for child process"))?
+                        .expect("Status must be set");
+                    exit_status_into_lua_table(lua, status)
+                }
+            })?
+            .with_async_function("status", move |lua, ()| {
+                let status = *status_rx.borrow();
+                async move {
+                    match status {
+                        Some(status) => Ok(Some(exit_status_into_lua_table(lua, status)?)),
+                        None => Ok(None),
+                    }
+                }
+            })?
+            .build_readonly()
+    }
+}
+
+fn as_child_stdio(kind: ProcessSpawnOptionsStdioKind) -> Stdio {
+    match kind {
+        ProcessSpawnOptionsStdioKind::Default => Stdio::piped(),
+        ProcessSpawnOptionsStdioKind::Forward | ProcessSpawnOptionsStdioKind::Inherit => {
+            Stdio::inherit()
+        }
+        ProcessSpawnOptionsStdioKind::None => Stdio::null(),
+    }
+}
+
+fn exit_status_into_lua_table(lua: &Lua, status: ExitStatus) -> LuaResult<LuaTable<'_>> {
+    let signal = ProcessSignal::from_exit_status(&status);
+    let code = exit_code_from_status(&status).unwrap_or(1);
+    TableBuilder::new(lua)?
+        .with_value("ok", code == 0)?
+        .with_value("code", code)?
+        .with_value("signal", signal.map(|s| s.name()))?
+        .build_readonly()
+}
+
+async fn drive_child(
+    mut child: Child,
+    mut kill_rx: mpsc::UnboundedReceiver<ChildKillRequest>,
+    status_tx: watch::Sender<Option<ExitStatus>>,
+) {
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status,
+            Some((signal, res_tx)) = kill_rx.recv() => {
+                res_tx.send(signal.send_to_child(&mut child)).ok();
+            }
+        }
+    };
+    if let Ok(status) = status {
+        status_tx.send(Some(status)).ok();
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    process::Stdio,
+};
+
+use mlua::prelude::*;
+
+use super::options::{ProcessSpawnOptions, ProcessSpawnOptionsStdioKind};
+
+/**
+    Spawns a child process that is detached from the current
+    process, and returns its process id without waiting for it.
+
+    On unix platforms the child process is started in a new session, which
+    also gives it a new process group, meaning that it will keep running after
+    the current process exits and will not receive signals sent to its terminal.
+*/
+pub(super) fn spawn_detached(
+    program: String,
+    args: Option<Vec<String>>,
+    mut options: ProcessSpawnOptions,
+) -> LuaResult<u32> {
+    if options.stdio.stdin.is_some() {
+        return Err(LuaError::runtime(
+            "Option 'stdin' is not supported for detached child processes",
+        ));
+    }
+    if options.timeout.is_some() {
+        return Err(LuaError::runtime(
+            "Option 'timeout' is not supported for detached child processes",
+        ));
+    }
+
+    let detached = options.detached.take().unwrap_or_default();
+    let stdout = match &detached.stdout {
+        Some(path) => Stdio::from(open_log_file(path)?),
+        None => as_detached_stdio(options.stdio.stdout),
+    };
+    let stderr = match &detached.stderr {
+        Some(path) => Stdio::from(open_log_file(path)?),
+        None => as_detached_stdio(options.stdio.stderr),
+    };
+
+    let mut cmd = options.into_command(program, args);
+    cmd.stdin(Stdio::null()).stdout(stdout).stderr(stderr);
+
+    #[cfg(unix)]
+    {
+        // SAFETY: Only async-signal-safe functions are called in the closure
+        unsafe {
+            cmd.pre_exec(|| match libc::setsid() {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+
+    // NOTE: Dropping the child here does not kill it, and
+    // tokio will reap it for us in the background once it exits
+    let child = cmd.spawn()?;
+    child
+        .id()
+        .ok_or_else(|| LuaError::runtime("Detached child process exited before it could start"))
+}
+
+fn as_detached_stdio(kind: ProcessSpawnOptionsStdioKind) -> Stdio {
+    match kind {
+        ProcessSpawnOptionsStdioKind::Forward | ProcessSpawnOptionsStdioKind::Inherit => {
+            Stdio::inherit()
+        }
+        ProcessSpawnOptionsStdioKind::Default | ProcessSpawnOptionsStdioKind::None => Stdio::null(),
+    }
+}
+
+fn open_log_file(path: &Path) -> LuaResult<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| {
+            LuaError::RuntimeError(format!(
+                "Failed to open log file '{}' - {e}",
+                path.display()
+            ))
+        })
+}
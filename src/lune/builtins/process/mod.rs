@@ -2,27 +2,48 @@ use std::{
     env::{self, consts},
     path,
     process::Stdio,
+    time::Instant,
 };
 
 use dunce::canonicalize;
 use mlua::prelude::*;
 use os_str_bytes::RawOsString;
-use tokio::io::AsyncWriteExt;
+use tokio::{io::AsyncWriteExt, task};
 
 use crate::lune::{scheduler::Scheduler, util::TableBuilder};
 
 mod tee_writer;
 
+mod child;
+use child::ProcessChild;
+
+mod detached;
+use detached::spawn_detached;
+
+mod on_exit;
+use on_exit::process_on_exit;
+
+mod on_signal;
+use on_signal::process_on_signal;
+
 mod options;
 use options::ProcessSpawnOptions;
 
-mod wait_for_child;
-use wait_for_child::{wait_for_child, WaitForChildResult};
+mod pipeline;
+use pipeline::{spawn_pipeline, ProcessPipelineStage};
+
+mod pty;
 
-const PROCESS_EXIT_IMPL_LUA: &str = r#"
-exit(...)
-yield()
-"#;
+mod signal;
+use signal::ProcessSignal;
+
+mod usage;
+
+mod which;
+use which::process_which;
+
+mod wait_for_child;
+use wait_for_child::{use_own_process_group, wait_for_child, WaitForChildResult};
 
 pub fn create(lua: &'static Lua) -> LuaResult<LuaTable> {
     let cwd_str = {
@@ -37,6 +58,12 @@ pub fn create(lua: &'static Lua) -> LuaResult<LuaTable> {
     // Create constants for OS & processor architecture
     let os = lua.create_string(&consts::OS.to_lowercase())?;
     let arch = lua.create_string(&consts::ARCH.to_lowercase())?;
+    // Create constants for the process ids and executable path
+    let pid = std::process::id();
+    let ppid = parent_process_id();
+    let exe_path = env::current_exe()
+        .ok()
+        .map(|path| path.to_string_lossy().to_string());
     // Create readonly args array
     let args_vec = lua
         .app_data_ref::<Vec<String>>()
@@ -55,42 +82,56 @@ pub fn create(lua: &'static Lua) -> LuaResult<LuaTable> {
                 .build_readonly()?,
         )?
         .build_readonly()?;
-    // Create our process exit function, this is a bit involved since
-    // we have no way to yield from c / rust, we need to load a lua
-    // chunk that will set the exit code and yield for us instead
-    let coroutine_yield = lua
-        .globals()
-        .get::<_, LuaTable>("coroutine")?
-        .get::<_, LuaFunction>("yield")?;
-    let set_scheduler_exit_code = lua.create_function(|lua, code: Option<u8>| {
-        let sched = lua
-            .app_data_ref::<&Scheduler>()
-            .expect("Lua struct is missing scheduler");
-        sched.set_exit_code(code.unwrap_or_default());
-        Ok(())
-    })?;
-    let process_exit = lua
-        .load(PROCESS_EXIT_IMPL_LUA)
-        .set_name("=process.exit")
-        .set_environment(
-            TableBuilder::new(lua)?
-                .with_value("yield", coroutine_yield)?
-                .with_value("exit", set_scheduler_exit_code)?
-                .build_readonly()?,
-        )
-        .into_function()?;
     // Create the full process table
     TableBuilder::new(lua)?
         .with_value("os", os)?
         .with_value("arch", arch)?
         .with_value("args", args_tab)?
         .with_value("cwd", cwd_str)?
+        .with_value("pid", pid)?
+        .with_value("ppid", ppid)?
+        .with_value("exePath", exe_path)?
         .with_value("env", env_tab)?
-        .with_value("exit", process_exit)?
+        .with_function("exit", process_exit)?
+        .with_function("onExit", process_on_exit)?
+        .with_function("which", process_which)?
+        .with_function("kill", process_kill)?
         .with_async_function("spawn", process_spawn)?
+        .with_async_function("create", process_create)?
+        .with_async_function("pipeline", process_pipeline)?
+        .with_function("onSignal", process_on_signal)?
         .build_readonly()
 }
 
+fn process_kill(_: &Lua, (pid, signal): (u32, ProcessSignal)) -> LuaResult<bool> {
+    match signal.send_to(pid) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(LuaError::RuntimeError(format!(
+            "Failed to send {signal} to process {pid} - {e}"
+        ))),
+    }
+}
+
+fn process_exit(lua: &Lua, code: Option<u8>) -> LuaResult<()> {
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    Err(sched.exit(code.unwrap_or_default()))
+}
+
+fn parent_process_id() -> Option<u32> {
+    #[cfg(unix)]
+    {
+        // SAFETY: getppid always succeeds and has no memory safety requirements
+        u32::try_from(unsafe { libc::getppid() }).ok()
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
 fn process_env_get<'lua>(
     lua: &'lua Lua,
     (_, key): (LuaValue<'lua>, String),
@@ -164,41 +205,75 @@ async fn process_spawn(
     lua: &Lua,
     (program, args, options): (String, Option<Vec<String>>, ProcessSpawnOptions),
 ) -> LuaResult<LuaTable> {
+    options.ensure_no_pty()?;
+
+    if options.detached.is_some() {
+        let pid = spawn_detached(program, args, options)?;
+        return TableBuilder::new(lua)?
+            .with_value("pid", pid)?
+            .build_readonly();
+    }
+
     /*
         Spawn the new process in the background, letting the tokio
         runtime place it on a different thread if possible / necessary
 
-        Note that we have to use our scheduler here, we can't
-        be using tokio::task::spawn directly because our lua
-        scheduler would not drive those futures to completion
+        Note that we do not use our scheduler for this, since this lua
+        future is already being driven by it, and the scheduler can not
+        accept new background futures while it is resuming existing ones
     */
-    let sched = lua
-        .app_data_ref::<&Scheduler>()
-        .expect("Lua struct is missing scheduler");
-
-    let res = sched
-        .spawn(spawn_command(program, args, options))
+    let res = task::spawn(spawn_command(program, args, options))
         .await
         .expect("Failed to receive result of spawned process")?;
 
-    /*
-        NOTE: If an exit code was not given by the child process,
-        we default to 1 if it yielded any error output, otherwise 0
+    res.into_lua_table(lua)
+}
 
-        An exit code may be missing if the process was terminated by
-        some external signal, which is the only time we use this default
-    */
-    let code = res.status.code().unwrap_or(match res.stderr.is_empty() {
-        true => 0,
-        false => 1,
-    });
+async fn process_create(
+    lua: &'static Lua,
+    (program, args, options): (String, Option<Vec<String>>, ProcessSpawnOptions),
+) -> LuaResult<LuaTable<'static>> {
+    options.ensure_not_detached()?;
+
+    let child = ProcessChild::spawn(program, args, options).await?;
+    child.into_lua_table(lua)
+}
+
+async fn process_pipeline(
+    lua: &Lua,
+    (stages, options): (Vec<ProcessPipelineStage>, ProcessSpawnOptions),
+) -> LuaResult<LuaTable<'_>> {
+    options.ensure_no_pty()?;
+    options.ensure_not_detached()?;
+
+    let results = task::spawn(spawn_pipeline(stages, options))
+        .await
+        .expect("Failed to receive result of spawned pipeline")?;
+
+    // NOTE: Similar to `set -o pipefail` in shells, the pipeline
+    // exits with the code of the last stage that did not succeed
+    let code = results
+        .iter()
+        .rev()
+        .map(|res| res.code())
+        .find(|code| *code != 0)
+        .unwrap_or_default();
+    let stdout = results
+        .last()
+        .map(|res| lua.create_string(&res.stdout))
+        .transpose()?;
+
+    let stages = lua.create_table_with_capacity(results.len(), 0)?;
+    for res in results {
+        stages.raw_push(res.into_lua_table(lua)?)?;
+    }
+    stages.set_readonly(true);
 
-    // Construct and return a readonly lua table with results
     TableBuilder::new(lua)?
         .with_value("ok", code == 0)?
         .with_value("code", code)?
-        .with_value("stdout", lua.create_string(&res.stdout)?)?
-        .with_value("stderr", lua.create_string(&res.stderr)?)?
+        .with_value("stdout", stdout)?
+        .with_value("stages", stages)?
         .build_readonly()
 }
 
@@ -210,9 +285,11 @@ async fn spawn_command(
     let stdout = options.stdio.stdout;
     let stderr = options.stdio.stderr;
     let stdin = options.stdio.stdin.take();
+    let timeout = options.timeout;
 
-    let mut child = options
-        .into_command(program, args)
+    let started = Instant::now();
+    let mut command = options.into_command(program, args);
+    let mut child = use_own_process_group(&mut command, timeout)
         .stdin(match stdin.is_some() {
             true => Stdio::piped(),
             false => Stdio::null(),
@@ -226,5 +303,5 @@ async fn spawn_command(
         child_stdin.write_all(&stdin).await.into_lua_err()?;
     }
 
-    wait_for_child(child, stdout, stderr).await
+    wait_for_child(child, stdout, stderr, timeout, started).await
 }
@@ -0,0 +1,38 @@
+use mlua::prelude::*;
+
+use crate::lune::{scheduler::Scheduler, util::TableBuilder};
+
+pub fn process_on_exit(lua: &'static Lua, handler: LuaFunction) -> LuaResult<LuaTable<'static>> {
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    let id = sched.add_exit_hook(lua, handler)?;
+    create_connection(lua, id)
+}
+
+fn create_connection(lua: &Lua, id: usize) -> LuaResult<LuaTable<'_>> {
+    TableBuilder::new(lua)?
+        .with_function("disconnect", move |lua, ()| {
+            let sched = lua
+                .app_data_ref::<&Scheduler>()
+                .expect("Lua struct is missing scheduler");
+            sched.remove_exit_hook(lua, id)
+        })?
+        .with_metatable(
+            TableBuilder::new(lua)?
+                .with_function(
+                    LuaMetaMethod::Index.name(),
+                    move |lua, (_, key): (LuaValue, String)| match key.as_str() {
+                        "connected" => {
+                            let sched = lua
+                                .app_data_ref::<&Scheduler>()
+                                .expect("Lua struct is missing scheduler");
+                            Ok(Some(sched.has_exit_hook(id)))
+                        }
+                        _ => Ok(None),
+                    },
+                )?
+                .build_readonly()?,
+        )?
+        .build_readonly()
+}
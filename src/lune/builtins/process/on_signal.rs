@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use mlua::prelude::*;
+
+use crate::lune::{
+    scheduler::Scheduler,
+    util::{traits::LuaEmitErrorExt, TableBuilder},
+};
+
+use super::signal::ProcessSignal;
+
+/**
+    Handlers connected using `process.onSignal`, stored in Lua app data.
+
+    A signal that has an entry in the handlers map is being listened
+    for, even if all of its handlers have since been disconnected.
+*/
+#[derive(Debug, Default)]
+struct ProcessSignalHandlers {
+    next_id: usize,
+    handlers: HashMap<ProcessSignal, Vec<(usize, LuaRegistryKey)>>,
+    #[cfg(unix)]
+    replaced: HashMap<ProcessSignal, ProcessSignalAction>,
+}
+
+/**
+    An action for a signal that was replaced by the default action, since all of the
+    handlers for the signal were disconnected, which is installed again once a new
+    handler gets connected - this is the action that tokio uses to listen for signals.
+*/
+#[cfg(unix)]
+struct ProcessSignalAction(libc::sigaction);
+
+#[cfg(unix)]
+impl std::fmt::Debug for ProcessSignalAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProcessSignalAction")
+    }
+}
+
+#[cfg(unix)]
+impl ProcessSignalAction {
+    /**
+        Replaces the current action for the given signal with the default action,
+        such as exiting the process, and returns the action that was replaced.
+    */
+    fn replace_with_default(signal: ProcessSignal) -> Option<Self> {
+        // SAFETY: Both structs are plain data, and the default action does not
+        // run any code in our process, so it can not break any invariants there
+        unsafe {
+            let mut default: libc::sigaction = std::mem::zeroed();
+            default.sa_sigaction = libc::SIG_DFL;
+            libc::sigemptyset(&mut default.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            match libc::sigaction(signal.as_raw(), &default, &mut previous) {
+                0 => Some(Self(previous)),
+                _ => None,
+            }
+        }
+    }
+
+    /**
+        Installs this action for the given signal again.
+    */
+    fn install(&self, signal: ProcessSignal) {
+        // SAFETY: This action was given to us by the OS when it was replaced, so it is valid
+        unsafe {
+            libc::sigaction(signal.as_raw(), &self.0, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(unix)]
+struct ProcessSignalListener(tokio::signal::unix::Signal);
+
+#[cfg(unix)]
+impl ProcessSignalListener {
+    fn new(signal: ProcessSignal) -> std::io::Result<Self> {
+        use tokio::signal::unix::{signal as listen, SignalKind};
+        listen(SignalKind::from_raw(signal.as_raw())).map(Self)
+    }
+
+    async fn recv(&mut self) -> Option<()> {
+        self.0.recv().await
+    }
+}
+
+#[cfg(windows)]
+struct ProcessSignalListener(tokio::signal::windows::CtrlC);
+
+#[cfg(windows)]
+impl ProcessSignalListener {
+    fn new(signal: ProcessSignal) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        match signal {
+            ProcessSignal::Int => tokio::signal::windows::ctrl_c().map(Self),
+            _ => Err(Error::new(
+                ErrorKind::Unsupported,
+                "only SIGINT is supported on this platform",
+            )),
+        }
+    }
+
+    async fn recv(&mut self) -> Option<()> {
+        self.0.recv().await
+    }
+}
+
+pub fn process_on_signal(
+    lua: &'static Lua,
+    (signal, handler): (String, LuaFunction),
+) -> LuaResult<LuaTable<'static>> {
+    let signal = signal.parse::<ProcessSignal>()?;
+
+    if lua.app_data_ref::<ProcessSignalHandlers>().is_none() {
+        lua.set_app_data(ProcessSignalHandlers::default());
+    }
+
+    let is_listening = lua
+        .app_data_ref::<ProcessSignalHandlers>()
+        .expect("Missing signal handlers in Lua app data")
+        .handlers
+        .contains_key(&signal);
+    #[cfg(unix)]
+    if let Some(action) = lua
+        .app_data_mut::<ProcessSignalHandlers>()
+        .expect("Missing signal handlers in Lua app data")
+        .replaced
+        .remove(&signal)
+    {
+        action.install(signal);
+    }
+    if !is_listening {
+        let listener = ProcessSignalListener::new(signal)
+            .map_err(|e| LuaError::RuntimeError(format!("Failed to listen for {signal} - {e}")))?;
+        let sched = lua
+            .app_data_ref::<&Scheduler>()
+            .expect("Lua struct is missing scheduler");
+        sched.spawn_local_detached(listen_for_signal(lua, signal, listener));
+    }
+
+    let key = lua.create_registry_value(handler)?;
+    let id = {
+        let mut handlers = lua
+            .app_data_mut::<ProcessSignalHandlers>()
+            .expect("Missing signal handlers in Lua app data");
+        let id = handlers.next_id;
+        handlers.next_id += 1;
+        handlers.handlers.entry(signal).or_default().push((id, key));
+        id
+    };
+
+    create_connection(lua, signal, id)
+}
+
+/**
+    Restores the default action for the given signal, which is used while
+    no handlers for it are connected, unless it has already been restored.
+
+    Note that the listener for the signal keeps running, since it can not
+    be stopped, but it will no longer receive signals until the action that
+    it uses gets installed again when a new handler is connected.
+*/
+#[cfg(unix)]
+fn restore_default_action(lua: &Lua, signal: ProcessSignal) {
+    use std::collections::hash_map;
+
+    let mut handlers = lua
+        .app_data_mut::<ProcessSignalHandlers>()
+        .expect("Missing signal handlers in Lua app data");
+    if let hash_map::Entry::Vacant(entry) = handlers.replaced.entry(signal) {
+        if let Some(action) = ProcessSignalAction::replace_with_default(signal) {
+            entry.insert(action);
+        }
+    }
+}
+
+/**
+    Listens for the given signal until the scheduler has run to completion.
+
+    Handlers are resumed as new threads, and if a signal is received while no
+    handlers are connected, the default behavior for the signal is kept - signals
+    that terminate the process by default make the scheduler exit with the code
+    128 + signal number, and any other signals are raised again with the default action.
+*/
+async fn listen_for_signal(
+    lua: &'static Lua,
+    signal: ProcessSignal,
+    mut listener: ProcessSignalListener,
+) {
+    while listener.recv().await.is_some() {
+        let sched = lua
+            .app_data_ref::<&Scheduler>()
+            .expect("Lua struct is missing scheduler");
+
+        let handlers = lua
+            .app_data_ref::<ProcessSignalHandlers>()
+            .expect("Missing signal handlers in Lua app data")
+            .handlers
+            .get(&signal)
+            .map(|handlers| {
+                handlers
+                    .iter()
+                    .map(|(_, key)| lua.registry_value::<LuaFunction>(key))
+                    .collect::<LuaResult<Vec<_>>>()
+            })
+            .transpose()
+            .expect("Failed to get signal handler from registry")
+            .unwrap_or_default();
+
+        if handlers.is_empty() {
+            // NOTE: Restoring the default action here also means that receiving the
+            // signal again while exit hooks are running will exit the process right away
+            #[cfg(unix)]
+            restore_default_action(lua, signal);
+            // NOTE: Signals such as SIGWINCH are ignored by default, and raising
+            // them again does nothing, so we must keep listening after this
+            #[cfg(unix)]
+            if !signal.terminates_by_default() {
+                // SAFETY: Raising a signal is always safe, the default action runs right away
+                unsafe {
+                    libc::raise(signal.as_raw());
+                }
+                continue;
+            }
+            // Exit the same way that process.exit does, instead of letting the
+            // default action exit right away, so that exit hooks still run and
+            // the terminal is restored to its original mode before exiting
+            if !sched.has_exit_code() {
+                sched.set_exit_code(signal.exit_code());
+            }
+            continue;
+        }
+
+        for handler in handlers {
+            if let Err(e) = sched.push_back(lua, handler, signal.name()) {
+                lua.emit_error(e);
+            }
+        }
+    }
+}
+
+fn is_connected(lua: &Lua, signal: ProcessSignal, id: usize) -> bool {
+    lua.app_data_ref::<ProcessSignalHandlers>()
+        .and_then(|handlers| {
+            handlers
+                .handlers
+                .get(&signal)
+                .map(|handlers| handlers.iter().any(|(i, _)| *i == id))
+        })
+        .unwrap_or_default()
+}
+
+fn disconnect(lua: &Lua, signal: ProcessSignal, id: usize) -> LuaResult<()> {
+    let removed = lua
+        .app_data_mut::<ProcessSignalHandlers>()
+        .and_then(|mut handlers| {
+            let handlers = handlers.handlers.get_mut(&signal)?;
+            let index = handlers.iter().position(|(i, _)| *i == id)?;
+            let key = handlers.remove(index).1;
+            Some((key, handlers.is_empty()))
+        });
+    if let Some((key, was_last)) = removed {
+        lua.remove_registry_value(key)?;
+        // NOTE: Signals are only delivered to our listener when lua threads yield, so
+        // without the default action, a thread that never yields could not be
+        // interrupted at all, not even by pressing Ctrl+C in the terminal
+        #[cfg(unix)]
+        if was_last {
+            restore_default_action(lua, signal);
+        }
+        #[cfg(not(unix))]
+        let _ = was_last;
+    }
+    Ok(())
+}
+
+fn create_connection(lua: &Lua, signal: ProcessSignal, id: usize) -> LuaResult<LuaTable<'_>> {
+    TableBuilder::new(lua)?
+        .with_function("disconnect", move |lua, ()| disconnect(lua, signal, id))?
+        .with_metatable(
+            TableBuilder::new(lua)?
+                .with_function(
+                    LuaMetaMethod::Index.name(),
+                    move |lua, (_, key): (LuaValue, String)| match key.as_str() {
+                        "connected" => Ok(Some(is_connected(lua, signal, id))),
+                        _ => Ok(None),
+                    },
+                )?
+                .build_readonly()?,
+        )?
+        .build_readonly()
+}
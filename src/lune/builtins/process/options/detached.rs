@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use mlua::prelude::*;
+
+/**
+    Options for spawning a child process that is detached from the current process.
+
+    Given as `true` to discard all output of the child process, or as a table
+    with `stdout` and `stderr` paths to log files that output is appended to.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessSpawnOptionsDetached {
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+}
+
+impl<'lua> FromLua<'lua> for ProcessSpawnOptionsDetached {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let LuaValue::Table(t) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessSpawnOptionsDetached",
+                message: Some(format!(
+                    "Invalid detached options - expected table, got {}",
+                    value.type_name()
+                )),
+            });
+        };
+        Ok(Self {
+            stdout: t
+                .get::<_, Option<String>>("stdout")
+                .context("Invalid value for detached option 'stdout' - expected a file path")?
+                .map(PathBuf::from),
+            stderr: t
+                .get::<_, Option<String>>("stderr")
+                .context("Invalid value for detached option 'stderr' - expected a file path")?
+                .map(PathBuf::from),
+        })
+    }
+}
@@ -2,17 +2,26 @@ use std::{
     collections::HashMap,
     env::{self},
     path::PathBuf,
+    time::Duration,
 };
 
 use directories::UserDirs;
 use mlua::prelude::*;
 use tokio::process::Command;
 
+use super::signal::ProcessSignal;
+
+mod detached;
 mod kind;
+mod pty;
 mod stdio;
+mod timeout;
 
+pub(super) use detached::*;
 pub(super) use kind::*;
+pub(super) use pty::*;
 pub(super) use stdio::*;
+pub(super) use timeout::*;
 
 #[derive(Debug, Clone, Default)]
 pub(super) struct ProcessSpawnOptions {
@@ -20,10 +29,13 @@ pub(super) struct ProcessSpawnOptions {
     pub envs: HashMap<String, String>,
     pub shell: Option<String>,
     pub stdio: ProcessSpawnOptionsStdio,
+    pub timeout: Option<ProcessSpawnOptionsTimeout>,
+    pub pty: Option<ProcessSpawnOptionsPty>,
+    pub detached: Option<ProcessSpawnOptionsDetached>,
 }
 
 impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
-    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
         let mut this = Self::default();
         let value = match value {
             LuaValue::Nil => return Ok(this),
@@ -133,11 +145,113 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
             }
         }
 
+        /*
+            If we got a timeout, also parse the signal to send when the timeout is
+            reached, and the grace period before forcefully killing the child - note
+            that these two options do nothing without a timeout, so we error instead
+            of silently ignoring them, which would most likely be a mistake
+        */
+        if let Some(duration) = parse_duration(&value, "timeout")? {
+            this.timeout = Some(ProcessSpawnOptionsTimeout {
+                duration,
+                signal: value
+                    .get::<_, ProcessSignal>("killSignal")
+                    .context("Invalid value for option 'killSignal'")?,
+                grace_period: parse_duration(&value, "killGracePeriod")?
+                    .unwrap_or(DEFAULT_KILL_GRACE_PERIOD),
+            });
+        } else {
+            for key in ["killSignal", "killGracePeriod"] {
+                if !value.get::<_, LuaValue>(key)?.is_nil() {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Option '{key}' can only be used together with option 'timeout'"
+                    )));
+                }
+            }
+        }
+
+        /*
+            If we got a pty option, the child process should be attached to a
+            pseudo-terminal, optionally with a specific initial terminal size
+        */
+        match value.get("pty")? {
+            LuaValue::Nil | LuaValue::Boolean(false) => {}
+            LuaValue::Boolean(true) => this.pty = Some(ProcessSpawnOptionsPty::default()),
+            value @ LuaValue::Table(_) => {
+                this.pty = Some(ProcessSpawnOptionsPty::from_lua(value, lua)?);
+            }
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'pty' - expected 'boolean' or 'table', got '{}'",
+                    value.type_name()
+                )))
+            }
+        }
+
+        /*
+            If we got a detached option, the child process should outlive the current
+            process, optionally with its output being redirected to log files
+        */
+        match value.get("detached")? {
+            LuaValue::Nil | LuaValue::Boolean(false) => {}
+            LuaValue::Boolean(true) => this.detached = Some(ProcessSpawnOptionsDetached::default()),
+            value @ LuaValue::Table(_) => {
+                this.detached = Some(ProcessSpawnOptionsDetached::from_lua(value, lua)?);
+            }
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'detached' - expected 'boolean' or 'table', got '{}'",
+                    value.type_name()
+                )))
+            }
+        }
+
         Ok(this)
     }
 }
 
+fn parse_duration(value: &LuaTable, key: &str) -> LuaResult<Option<Duration>> {
+    match value.get(key)? {
+        LuaValue::Nil => Ok(None),
+        LuaValue::Integer(i) if i > 0 => Ok(Some(Duration::from_secs(i as u64))),
+        LuaValue::Number(n) if n.is_finite() && n > 0.0 => Ok(Some(Duration::from_secs_f64(n))),
+        LuaValue::Integer(_) | LuaValue::Number(_) => Err(LuaError::RuntimeError(format!(
+            "Invalid value for option '{key}' - expected a positive number of seconds"
+        ))),
+        value => Err(LuaError::RuntimeError(format!(
+            "Invalid type for option '{key}' - expected 'number', got '{}'",
+            value.type_name()
+        ))),
+    }
+}
+
 impl ProcessSpawnOptions {
+    /**
+        Makes sure that the `pty` option was not given, since only child
+        processes created using `process.create` can be attached to a pty.
+    */
+    pub fn ensure_no_pty(&self) -> LuaResult<()> {
+        match self.pty {
+            None => Ok(()),
+            Some(_) => Err(LuaError::runtime(
+                "Option 'pty' is only supported by process.create",
+            )),
+        }
+    }
+
+    /**
+        Makes sure that the `detached` option was not given, since only child
+        processes spawned using `process.spawn` can be detached.
+    */
+    pub fn ensure_not_detached(&self) -> LuaResult<()> {
+        match self.detached {
+            None => Ok(()),
+            Some(_) => Err(LuaError::runtime(
+                "Option 'detached' is only supported by process.spawn",
+            )),
+        }
+    }
+
     pub fn into_command(self, program: impl Into<String>, args: Option<Vec<String>>) -> Command {
         let mut program = program.into();
 
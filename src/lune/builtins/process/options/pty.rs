@@ -0,0 +1,45 @@
+use mlua::prelude::*;
+
+/**
+    Options for allocating a pseudo-terminal for a child process.
+
+    Given as `true` for a terminal with the default size, or as
+    a table with `cols` and `rows` for a terminal with a custom size.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessSpawnOptionsPty {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for ProcessSpawnOptionsPty {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl<'lua> FromLua<'lua> for ProcessSpawnOptionsPty {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let LuaValue::Table(t) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessSpawnOptionsPty",
+                message: Some(format!(
+                    "Invalid pty size - expected table, got {}",
+                    value.type_name()
+                )),
+            });
+        };
+        let default = Self::default();
+        Ok(Self {
+            cols: t
+                .get::<_, Option<u16>>("cols")
+                .context("Invalid value for pty size 'cols'")?
+                .unwrap_or(default.cols),
+            rows: t
+                .get::<_, Option<u16>>("rows")
+                .context("Invalid value for pty size 'rows'")?
+                .unwrap_or(default.rows),
+        })
+    }
+}
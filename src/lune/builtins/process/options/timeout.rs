@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use super::ProcessSignal;
+
+/**
+    How long to wait for a child process to exit after sending it the
+    kill signal, when no grace period was given, before forcefully killing it.
+*/
+pub const DEFAULT_KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/**
+    Options for terminating a child process that has been running for too long.
+
+    Once `duration` has passed, `signal` is sent to the child process. If the
+    child is still running once the grace period has also passed, the child
+    process is then forcefully killed, since it may be ignoring the signal.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessSpawnOptionsTimeout {
+    pub duration: Duration,
+    pub signal: ProcessSignal,
+    pub grace_period: Duration,
+}
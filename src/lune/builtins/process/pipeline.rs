@@ -0,0 +1,166 @@
+use std::{process::Stdio, time::Instant};
+
+use futures_util::future::join_all;
+use mlua::prelude::*;
+use tokio::{io::AsyncWriteExt, process::Child, task};
+
+use super::{
+    options::{ProcessSpawnOptions, ProcessSpawnOptionsStdioKind},
+    wait_for_child::{use_own_process_group, wait_for_child, WaitForChildResult},
+};
+
+/**
+    A single stage of a process pipeline, given as `{ program, args }`.
+*/
+#[derive(Debug, Clone)]
+pub(super) struct ProcessPipelineStage {
+    program: String,
+    args: Option<Vec<String>>,
+}
+
+impl<'lua> FromLua<'lua> for ProcessPipelineStage {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let LuaValue::Table(t) = value else {
+            return Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessPipelineStage",
+                message: Some(format!(
+                    "Invalid pipeline stage - expected table, got {}",
+                    value.type_name()
+                )),
+            });
+        };
+        let program = t
+            .get::<_, Option<String>>(1)?
+            .ok_or_else(|| LuaError::runtime("Invalid pipeline stage - missing program"))?;
+        let args = t
+            .get::<_, Option<Vec<String>>>(2)
+            .context("Invalid pipeline stage - args must be a list of strings")?;
+        Ok(Self { program, args })
+    }
+}
+
+/**
+    Spawns all stages of a pipeline, with the stdout of each
+    stage connected directly to the stdin of the next stage.
+
+    The stdin given in `options` is written to the first stage, the stdout
+    kind in `options` is used for the last stage, and the stderr kind is
+    used for every stage. All other options apply to every stage.
+*/
+pub(super) async fn spawn_pipeline(
+    stages: Vec<ProcessPipelineStage>,
+    mut options: ProcessSpawnOptions,
+) -> LuaResult<Vec<WaitForChildResult>> {
+    if stages.is_empty() {
+        return Err(LuaError::runtime(
+            "Invalid pipeline - expected at least one stage",
+        ));
+    }
+
+    let stdout = options.stdio.stdout;
+    let stderr = options.stdio.stderr;
+    let stdin = options.stdio.stdin.take();
+    let timeout = options.timeout;
+
+    let started = Instant::now();
+    let mut children: Vec<Child> = Vec::with_capacity(stages.len());
+
+    // Make sure that we do not leave any stages running if any of them failed to spawn
+    if let Err(e) = spawn_stages(&mut children, stages, &options, stdin.is_some()) {
+        kill_and_wait_all(&mut children).await;
+        return Err(e);
+    }
+
+    /*
+        Write stdin to the first stage in the background, since the
+        first stage may not read all of it until later stages have
+        started consuming its output, and we need to wait for those
+    */
+    if let Some(stdin) = stdin {
+        let mut child_stdin = children[0].stdin.take().unwrap();
+        task::spawn(async move {
+            child_stdin.write_all(&stdin).await.ok();
+        });
+    }
+
+    /*
+        Wait for every stage, even if waiting for one of them failed, since the
+        remaining stages would otherwise keep running in the background - any stage
+        that failed is killed once it is dropped, which closes the pipes connected to
+        it, and lets the stages next to it exit as well, same as any failed stage would
+    */
+    let last = children.len() - 1;
+    join_all(children.into_iter().enumerate().map(|(index, child)| {
+        let stage_stdout = match index == last {
+            true => stdout,
+            false => ProcessSpawnOptionsStdioKind::None,
+        };
+        wait_for_child(child, stage_stdout, stderr, timeout, started)
+    }))
+    .await
+    .into_iter()
+    .collect()
+}
+
+/**
+    Spawns all stages of a pipeline, pushing them to `children` as they are spawned,
+    so that any stages that were spawned can be cleaned up if a later stage fails.
+*/
+fn spawn_stages(
+    children: &mut Vec<Child>,
+    stages: Vec<ProcessPipelineStage>,
+    options: &ProcessSpawnOptions,
+    has_stdin: bool,
+) -> LuaResult<()> {
+    let stdout = options.stdio.stdout;
+    let stderr = options.stdio.stderr;
+    let timeout = options.timeout;
+
+    let last = stages.len() - 1;
+    let mut next_stdin: Option<Stdio> = None;
+
+    for (index, stage) in stages.into_iter().enumerate() {
+        let stage_stdin = match next_stdin.take() {
+            Some(piped) => piped,
+            None if has_stdin => Stdio::piped(),
+            None => Stdio::null(),
+        };
+        let stage_stdout = match index == last {
+            true => stdout.as_stdio(),
+            false => Stdio::piped(),
+        };
+
+        let mut command = options.clone().into_command(stage.program, stage.args);
+        let mut child = use_own_process_group(&mut command, timeout)
+            .stdin(stage_stdin)
+            .stdout(stage_stdout)
+            .stderr(stderr.as_stdio())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let child_stdout = match index == last {
+            true => None,
+            false => child.stdout.take(),
+        };
+        children.push(child);
+
+        if let Some(child_stdout) = child_stdout {
+            next_stdin = Some(child_stdout.try_into().into_lua_err()?);
+        }
+    }
+
+    Ok(())
+}
+
+/**
+    Kills all of the given child processes, and waits for them to exit.
+*/
+async fn kill_and_wait_all(children: &mut [Child]) {
+    for child in children.iter_mut() {
+        child.start_kill().ok();
+    }
+    for child in children.iter_mut() {
+        child.wait().await.ok();
+    }
+}
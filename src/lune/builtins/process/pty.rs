@@ -0,0 +1,271 @@
+use mlua::prelude::*;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    process::Command,
+};
+
+use super::options::ProcessSpawnOptionsPty;
+
+#[cfg(unix)]
+pub(super) use self::unix::ProcessPty;
+
+#[cfg(not(unix))]
+pub(super) use self::unsupported::ProcessPty;
+
+/**
+    Shared interface for a pseudo-terminal that a child process is attached to.
+*/
+pub(super) trait ProcessPtyExt: Sized {
+    /**
+        Opens a new pseudo-terminal and attaches the given command to it, meaning
+        that the stdin, stdout and stderr of the command will all be the terminal.
+    */
+    fn attach(cmd: &mut Command, size: ProcessSpawnOptionsPty) -> LuaResult<Self>;
+
+    fn resize(&self, size: ProcessSpawnOptionsPty) -> LuaResult<()>;
+
+    fn reader(&self) -> Box<dyn AsyncRead + Send + Unpin>;
+
+    fn writer(&self) -> Box<dyn AsyncWrite + Send + Unpin>;
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        io,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        pin::Pin,
+        process::Stdio,
+        sync::Arc,
+        task::{ready, Context, Poll},
+    };
+
+    use tokio::io::{unix::AsyncFd, ReadBuf};
+
+    use super::*;
+
+    /// The end-of-transmission character, same as pressing Ctrl+D in a terminal
+    const EOT: u8 = 0x04;
+
+    #[derive(Debug, Clone)]
+    pub struct ProcessPty {
+        master: Arc<AsyncFd<OwnedFd>>,
+    }
+
+    impl ProcessPtyExt for ProcessPty {
+        fn attach(cmd: &mut Command, size: ProcessSpawnOptionsPty) -> LuaResult<Self> {
+            let (master, slave) = open_pty(size)
+                .map_err(|e| LuaError::RuntimeError(format!("Failed to open pty - {e}")))?;
+
+            cmd.stdin(Stdio::from(slave.try_clone()?))
+                .stdout(Stdio::from(slave.try_clone()?))
+                .stderr(Stdio::from(slave));
+
+            // SAFETY: Only async-signal-safe functions are called in the closure, making
+            // the child a session leader with the pty as its controlling terminal
+            unsafe {
+                cmd.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+
+            Ok(Self {
+                master: Arc::new(AsyncFd::new(master)?),
+            })
+        }
+
+        fn resize(&self, size: ProcessSpawnOptionsPty) -> LuaResult<()> {
+            let winsize = as_winsize(size);
+            // SAFETY: The master fd is kept open for as long as we hold onto it
+            match unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } {
+                -1 => Err(LuaError::RuntimeError(format!(
+                    "Failed to resize pty - {}",
+                    io::Error::last_os_error()
+                ))),
+                _ => Ok(()),
+            }
+        }
+
+        fn reader(&self) -> Box<dyn AsyncRead + Send + Unpin> {
+            Box::new(ProcessPtyStream(Arc::clone(&self.master)))
+        }
+
+        fn writer(&self) -> Box<dyn AsyncWrite + Send + Unpin> {
+            Box::new(ProcessPtyStream(Arc::clone(&self.master)))
+        }
+    }
+
+    fn as_winsize(size: ProcessSpawnOptionsPty) -> libc::winsize {
+        libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+
+    fn check(res: libc::c_int) -> io::Result<libc::c_int> {
+        match res {
+            -1 => Err(io::Error::last_os_error()),
+            res => Ok(res),
+        }
+    }
+
+    fn set_cloexec(fd: RawFd) -> io::Result<()> {
+        // SAFETY: The fd is valid, and setting flags does not affect memory safety
+        unsafe {
+            let flags = check(libc::fcntl(fd, libc::F_GETFD))?;
+            check(libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC))?;
+        }
+        Ok(())
+    }
+
+    fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+        // SAFETY: The fd is valid, and setting flags does not affect memory safety
+        unsafe {
+            let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+            check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        }
+        Ok(())
+    }
+
+    /**
+        Opens a new pty, returning its master and slave file descriptors.
+
+        Both are set to close on exec, the slave will be duplicated onto
+        the stdio of the child process, which clears this flag again.
+    */
+    fn open_pty(size: ProcessSpawnOptionsPty) -> io::Result<(OwnedFd, OwnedFd)> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        let winsize = as_winsize(size);
+
+        // SAFETY: All pointers are valid for the duration of the call, and
+        // on success we take ownership of the two newly opened descriptors
+        let (master, slave) = unsafe {
+            check(libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &winsize,
+            ))?;
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+
+        set_cloexec(master.as_raw_fd())?;
+        set_cloexec(slave.as_raw_fd())?;
+        set_nonblocking(master.as_raw_fd())?;
+
+        Ok((master, slave))
+    }
+
+    struct ProcessPtyStream(Arc<AsyncFd<OwnedFd>>);
+
+    impl AsyncRead for ProcessPtyStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            loop {
+                let mut guard = ready!(self.0.poll_read_ready(cx))?;
+                let unfilled = buf.initialize_unfilled();
+                let res = guard.try_io(|fd| {
+                    // SAFETY: The buffer is valid for writes of its full length
+                    let n = unsafe {
+                        libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr().cast(), unfilled.len())
+                    };
+                    match n {
+                        -1 => Err(io::Error::last_os_error()),
+                        n => Ok(n as usize),
+                    }
+                });
+                match res {
+                    Ok(Ok(n)) => {
+                        buf.advance(n);
+                        return Poll::Ready(Ok(()));
+                    }
+                    // NOTE: Reading from the master side of a pty fails with EIO once
+                    // all processes have closed the slave side, this means end of file
+                    Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => {
+                        return Poll::Ready(Ok(()));
+                    }
+                    Ok(Err(e)) => return Poll::Ready(Err(e)),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    impl AsyncWrite for ProcessPtyStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            loop {
+                let mut guard = ready!(self.0.poll_write_ready(cx))?;
+                let res = guard.try_io(|fd| {
+                    // SAFETY: The buffer is valid for reads of its full length
+                    let n = unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+                    match n {
+                        -1 => Err(io::Error::last_os_error()),
+                        n => Ok(n as usize),
+                    }
+                });
+                match res {
+                    Ok(res) => return Poll::Ready(res),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        /*
+            The master side of the pty is shared with the reader, so we can't close
+            it here - instead we send an end-of-transmission character, which
+            is what closing stdin means to programs running in a terminal
+        */
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.poll_write(cx, &[EOT]).map_ok(|_| ())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    pub enum ProcessPty {}
+
+    impl ProcessPtyExt for ProcessPty {
+        fn attach(_: &mut Command, _: ProcessSpawnOptionsPty) -> LuaResult<Self> {
+            Err(LuaError::runtime(
+                "Option 'pty' is not supported on this platform",
+            ))
+        }
+
+        fn resize(&self, _: ProcessSpawnOptionsPty) -> LuaResult<()> {
+            match *self {}
+        }
+
+        fn reader(&self) -> Box<dyn AsyncRead + Send + Unpin> {
+            match *self {}
+        }
+
+        fn writer(&self) -> Box<dyn AsyncWrite + Send + Unpin> {
+            match *self {}
+        }
+    }
+}
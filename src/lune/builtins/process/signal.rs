@@ -0,0 +1,358 @@
+use std::{fmt, process::ExitStatus, str::FromStr};
+
+use itertools::Itertools;
+use mlua::prelude::*;
+use tokio::process::Child;
+
+/**
+    A signal that can be sent to, or received by, a process.
+
+    Signal names are accepted with or without the `SIG` prefix,
+    and on unix platforms raw signal numbers are accepted too.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ProcessSignal {
+    Hup,
+    Int,
+    Quit,
+    Ill,
+    Trap,
+    Abrt,
+    Bus,
+    Fpe,
+    Kill,
+    Usr1,
+    Segv,
+    Usr2,
+    Pipe,
+    Alrm,
+    #[default]
+    Term,
+    Chld,
+    Cont,
+    Stop,
+    Tstp,
+    Winch,
+}
+
+impl ProcessSignal {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Hup,
+            Self::Int,
+            Self::Quit,
+            Self::Ill,
+            Self::Trap,
+            Self::Abrt,
+            Self::Bus,
+            Self::Fpe,
+            Self::Kill,
+            Self::Usr1,
+            Self::Segv,
+            Self::Usr2,
+            Self::Pipe,
+            Self::Alrm,
+            Self::Term,
+            Self::Chld,
+            Self::Cont,
+            Self::Stop,
+            Self::Tstp,
+            Self::Winch,
+        ]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hup => "SIGHUP",
+            Self::Int => "SIGINT",
+            Self::Quit => "SIGQUIT",
+            Self::Ill => "SIGILL",
+            Self::Trap => "SIGTRAP",
+            Self::Abrt => "SIGABRT",
+            Self::Bus => "SIGBUS",
+            Self::Fpe => "SIGFPE",
+            Self::Kill => "SIGKILL",
+            Self::Usr1 => "SIGUSR1",
+            Self::Segv => "SIGSEGV",
+            Self::Usr2 => "SIGUSR2",
+            Self::Pipe => "SIGPIPE",
+            Self::Alrm => "SIGALRM",
+            Self::Term => "SIGTERM",
+            Self::Chld => "SIGCHLD",
+            Self::Cont => "SIGCONT",
+            Self::Stop => "SIGSTOP",
+            Self::Tstp => "SIGTSTP",
+            Self::Winch => "SIGWINCH",
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn as_raw(self) -> libc::c_int {
+        match self {
+            Self::Hup => libc::SIGHUP,
+            Self::Int => libc::SIGINT,
+            Self::Quit => libc::SIGQUIT,
+            Self::Ill => libc::SIGILL,
+            Self::Trap => libc::SIGTRAP,
+            Self::Abrt => libc::SIGABRT,
+            Self::Bus => libc::SIGBUS,
+            Self::Fpe => libc::SIGFPE,
+            Self::Kill => libc::SIGKILL,
+            Self::Usr1 => libc::SIGUSR1,
+            Self::Segv => libc::SIGSEGV,
+            Self::Usr2 => libc::SIGUSR2,
+            Self::Pipe => libc::SIGPIPE,
+            Self::Alrm => libc::SIGALRM,
+            Self::Term => libc::SIGTERM,
+            Self::Chld => libc::SIGCHLD,
+            Self::Cont => libc::SIGCONT,
+            Self::Stop => libc::SIGSTOP,
+            Self::Tstp => libc::SIGTSTP,
+            Self::Winch => libc::SIGWINCH,
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn from_raw(raw: libc::c_int) -> Option<Self> {
+        Self::all().iter().copied().find(|s| s.as_raw() == raw)
+    }
+
+    /**
+        Checks if the default action for this signal is to terminate the process,
+        as opposed to ignoring the signal, or stopping or continuing the process.
+    */
+    pub fn terminates_by_default(self) -> bool {
+        !matches!(
+            self,
+            Self::Chld | Self::Cont | Self::Stop | Self::Tstp | Self::Winch
+        )
+    }
+
+    /**
+        Gets the exit code that a process terminated by this signal conventionally
+        exits with, which is 128 + signal number, when the process did not handle it.
+    */
+    pub fn exit_code(self) -> u8 {
+        #[cfg(unix)]
+        {
+            (128 + self.as_raw()) as u8
+        }
+        // NOTE: Only SIGINT can be listened for on other platforms
+        #[cfg(not(unix))]
+        {
+            130
+        }
+    }
+
+    /**
+        Sends this signal to the process with the given id.
+
+        Returns an error of kind [`NotFound`](std::io::ErrorKind::NotFound) if
+        there is no process with the given id. On platforms other than unix,
+        only signals that terminate the process are supported, and will
+        forcefully kill it.
+    */
+    pub fn send_to(self, pid: u32) -> std::io::Result<()> {
+        use std::io::{Error, ErrorKind};
+        // NOTE: Process id 0 refers to our own process group
+        // when sending signals, which is never what we want
+        if pid == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid process id"));
+        }
+        #[cfg(unix)]
+        {
+            let pid = libc::pid_t::try_from(pid)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid process id"))?;
+            self.kill_raw(pid)
+        }
+        #[cfg(not(unix))]
+        {
+            use std::process::{Command, Stdio};
+            if !matches!(self, Self::Kill | Self::Term | Self::Int) {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("{self} is not supported on this platform"),
+                ));
+            }
+            let status = Command::new("taskkill")
+                .args(["/PID", &pid.to_string(), "/F"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()?;
+            // NOTE: Taskkill exits with code 128 if the process was not found
+            match status.code() {
+                Some(0) => Ok(()),
+                Some(128) => Err(Error::new(ErrorKind::NotFound, "No such process")),
+                _ => Err(Error::new(ErrorKind::Other, "Failed to kill process")),
+            }
+        }
+    }
+
+    /**
+        Sends this signal using `kill`, where a negative
+        id refers to a process group instead of a process.
+    */
+    #[cfg(unix)]
+    fn kill_raw(self, id: libc::pid_t) -> std::io::Result<()> {
+        use std::io::{Error, ErrorKind};
+        // SAFETY: Sending a signal has no memory safety requirements, any
+        // invalid pid or signal number is reported back to us as an error
+        match unsafe { libc::kill(id, self.as_raw()) } {
+            0 => Ok(()),
+            _ => match Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::ESRCH) => {
+                    Err(Error::new(ErrorKind::NotFound, "No such process"))
+                }
+                e => Err(e),
+            },
+        }
+    }
+
+    /**
+        Sends this signal to every process in the process group of the given
+        child process, which must have been spawned as the leader of its own group.
+
+        The group id is given separately since the child process may already have
+        exited and been reaped, while processes that it spawned are still running.
+        On platforms other than unix, only the child process itself is signalled.
+    */
+    pub fn send_to_child_group(self, child: &mut Child, pgid: Option<u32>) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            let _ = child;
+            // NOTE: Group id 0 would signal our own process group
+            let Some(pgid) = pgid
+                .and_then(|id| libc::pid_t::try_from(id).ok())
+                .filter(|id| *id > 0)
+            else {
+                return Ok(());
+            };
+            match self.kill_raw(-pgid) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                // Every process in the group has already exited
+                _ => Ok(()),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = pgid;
+            self.send_to_child(child)
+        }
+    }
+
+    /**
+        Sends this signal to the given child process.
+
+        On platforms other than unix, only signals that terminate
+        the child process are supported, and will forcefully kill it.
+    */
+    pub fn send_to_child(self, child: &mut Child) -> std::io::Result<()> {
+        #[cfg(unix)]
+        {
+            match child.id().map(|pid| self.send_to(pid)) {
+                Some(Err(e)) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                // The child process has already exited and been reaped
+                _ => Ok(()),
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            use std::io::{Error, ErrorKind};
+            match self {
+                Self::Kill | Self::Term | Self::Int => child.start_kill(),
+                _ => Err(Error::new(
+                    ErrorKind::Unsupported,
+                    format!("{self} is not supported on this platform"),
+                )),
+            }
+        }
+    }
+
+    /**
+        Gets the signal that terminated a process, if any.
+
+        This will always be `None` on platforms other than unix.
+    */
+    pub fn from_exit_status(status: &ExitStatus) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            status.signal().and_then(Self::from_raw)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = status;
+            None
+        }
+    }
+}
+
+/**
+    Gets the exit code for a process from its exit status.
+
+    Processes that were terminated by a signal do not have an exit code, so
+    on unix platforms we follow the shell convention of 128 + signal number.
+*/
+pub fn exit_code_from_status(status: &ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.code().or_else(|| status.signal().map(|s| 128 + s))
+    }
+    #[cfg(not(unix))]
+    {
+        status.code()
+    }
+}
+
+impl fmt::Display for ProcessSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ProcessSignal {
+    type Err = LuaError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        Self::all()
+            .iter()
+            .copied()
+            .find(|signal| &signal.name()[3..] == name)
+            .ok_or_else(|| {
+                LuaError::RuntimeError(format!(
+                    "Invalid signal - got '{}', expected one of {}",
+                    s,
+                    ProcessSignal::all()
+                        .iter()
+                        .map(|s| format!("'{s}'"))
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+impl<'lua> FromLua<'lua> for ProcessSignal {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::String(s) => s.to_str()?.parse(),
+            #[cfg(unix)]
+            LuaValue::Integer(_) | LuaValue::Number(_) => {
+                let raw = libc::c_int::from_lua(value, lua)?;
+                Self::from_raw(raw).ok_or_else(|| {
+                    LuaError::RuntimeError(format!("Invalid signal - got number {raw}"))
+                })
+            }
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessSignal",
+                message: Some(format!(
+                    "Invalid signal - expected string, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
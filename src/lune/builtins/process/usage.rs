@@ -0,0 +1,155 @@
+use std::{
+    io,
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
+
+use mlua::prelude::*;
+use tokio::process::Child;
+
+use crate::lune::util::TableBuilder;
+
+/**
+    Resources used by a child process during its lifetime.
+
+    CPU time and peak memory usage are only available on Linux, and include
+    any descendants of the child process that it waited for before exiting.
+*/
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ProcessUsage {
+    pub wall_time: Duration,
+    pub user_time: Option<Duration>,
+    pub system_time: Option<Duration>,
+    pub peak_memory: Option<u64>,
+}
+
+impl ProcessUsage {
+    pub fn into_lua_table(self, lua: &Lua) -> LuaResult<LuaTable<'_>> {
+        TableBuilder::new(lua)?
+            .with_value("wallTime", self.wall_time.as_secs_f64())?
+            .with_value("userTime", self.user_time.map(|d| d.as_secs_f64()))?
+            .with_value("systemTime", self.system_time.map(|d| d.as_secs_f64()))?
+            .with_value("peakMemory", self.peak_memory)?
+            .build_readonly()
+    }
+}
+
+/**
+    Waits for the given child process to exit, and gets the resources it used.
+
+    The wall time is measured from the given instant, which
+    should be the time right before the child was spawned.
+*/
+pub(super) async fn wait_with_usage(
+    child: &mut Child,
+    started: Instant,
+) -> io::Result<(ExitStatus, ProcessUsage)> {
+    let rusage = match child.id() {
+        Some(pid) => wait_for_rusage(pid).await,
+        None => None,
+    };
+    let status = child.wait().await?;
+    let usage = ProcessUsage {
+        wall_time: started.elapsed(),
+        user_time: rusage.map(|r| r.user_time),
+        system_time: rusage.map(|r| r.system_time),
+        peak_memory: rusage.map(|r| r.peak_memory),
+    };
+    Ok((status, usage))
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+struct ChildRusage {
+    user_time: Duration,
+    system_time: Duration,
+    peak_memory: u64,
+}
+
+/**
+    Waits for the child process with the given id to exit, without reaping it,
+    and gets its resource usage. The caller is responsible for reaping it after.
+
+    This waits for the child process using a pidfd, which lets us wait without
+    blocking a thread and stop waiting at any time, such as when a timeout is
+    reached, and then uses the raw `waitid` syscall, which unlike its libc wrapper
+    and `wait4`, can give us resource usage while leaving the child process waitable,
+    meaning tokio can still reap it and keep track of its exit status as usual.
+
+    Note that pidfds require Linux 5.3 or newer, and no usage is given on older versions.
+*/
+#[cfg(target_os = "linux")]
+async fn wait_for_rusage(pid: u32) -> Option<ChildRusage> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use tokio::io::{unix::AsyncFd, Interest};
+
+    // SAFETY: Opening a pidfd has no memory safety requirements
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        return None;
+    }
+    // SAFETY: The pidfd was opened right above, and is not owned by anything else
+    let pidfd = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+    let pidfd = AsyncFd::with_interest(pidfd, Interest::READABLE).ok()?;
+
+    // NOTE: A pidfd becomes readable once the process has exited
+    loop {
+        let mut guard = pidfd.readable().await.ok()?;
+        match peek_rusage(pid) {
+            Ok(Some(rusage)) => return Some(rusage),
+            Ok(None) => guard.clear_ready(),
+            Err(_) => return None,
+        }
+    }
+}
+
+/**
+    Gets the resource usage of the child process with the given
+    id if it has exited, without reaping it, and without blocking.
+*/
+#[cfg(target_os = "linux")]
+fn peek_rusage(pid: u32) -> io::Result<Option<ChildRusage>> {
+    fn as_duration(time: libc::timeval) -> Duration {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    }
+
+    loop {
+        // SAFETY: Both structs are plain data that the kernel fills in for us
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: The pointers are valid for the duration of the call, and
+        // passing WNOWAIT means that the child process is never reaped here
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_waitid,
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info as *mut libc::siginfo_t,
+                libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+                &mut usage as *mut libc::rusage,
+            )
+        };
+        if res == 0 {
+            // NOTE: With WNOHANG, the pid is left as zero if the child has not exited yet
+            // SAFETY: The kernel always fills in the pid field for waitid
+            if unsafe { info.si_pid() } == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(ChildRusage {
+                user_time: as_duration(usage.ru_utime),
+                system_time: as_duration(usage.ru_stime),
+                // NOTE: The max resident set size is given in kilobytes on Linux
+                peak_memory: (usage.ru_maxrss as u64) * 1024,
+            }));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn wait_for_rusage(_: u32) -> Option<ChildRusage> {
+    None
+}
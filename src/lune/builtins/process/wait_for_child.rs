@@ -1,24 +1,84 @@
-use std::process::ExitStatus;
+use std::{
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
 
 use mlua::prelude::*;
 use tokio::{
-    io::{self, AsyncRead, AsyncReadExt},
-    process::Child,
-    task,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::{Child, Command},
+    sync::oneshot,
+    task::{self, JoinHandle},
+    time,
+};
+
+use crate::lune::util::TableBuilder;
+
+use super::{
+    options::{ProcessSpawnOptionsStdioKind, ProcessSpawnOptionsTimeout},
+    signal::{exit_code_from_status, ProcessSignal},
+    tee_writer::AsyncTeeWriter,
+    usage::{wait_with_usage, ProcessUsage},
 };
 
-use super::{options::ProcessSpawnOptionsStdioKind, tee_writer::AsyncTeeWriter};
+/**
+    How long to keep reading output after a child process was terminated because
+    of a timeout, since any processes that it spawned may still keep its pipes open.
+*/
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 pub(super) struct WaitForChildResult {
+    pub pid: Option<u32>,
     pub status: ExitStatus,
     pub stdout: Vec<u8>,
     pub stderr: Vec<u8>,
+    pub timed_out: bool,
+    pub usage: ProcessUsage,
+}
+
+impl WaitForChildResult {
+    /**
+        Gets the exit code of the child process.
+
+        If an exit code was not given by the child process, and we
+        also do not know which signal terminated it, we default to 1 if
+        it yielded any error output, otherwise 0
+
+        An exit code may be missing if the process was terminated by
+        some external signal, which is the only time we use this default
+    */
+    pub fn code(&self) -> i32 {
+        exit_code_from_status(&self.status).unwrap_or(match self.stderr.is_empty() {
+            true => 0,
+            false => 1,
+        })
+    }
+
+    pub fn into_lua_table(self, lua: &Lua) -> LuaResult<LuaTable<'_>> {
+        let code = self.code();
+        let signal = ProcessSignal::from_exit_status(&self.status);
+        TableBuilder::new(lua)?
+            .with_value("pid", self.pid)?
+            .with_value("ok", code == 0)?
+            .with_value("code", code)?
+            .with_value("signal", signal.map(|s| s.name()))?
+            .with_value("timedOut", self.timed_out)?
+            .with_value("stdout", lua.create_string(&self.stdout)?)?
+            .with_value("stderr", lua.create_string(&self.stderr)?)?
+            .with_value("usage", self.usage.into_lua_table(lua)?)?
+            .build_readonly()
+    }
 }
 
+/**
+    Reads all output from the given reader, unless told to stop early
+    using `stop`, in which case any output read until then is returned.
+*/
 async fn read_with_stdio_kind<R>(
     read_from: Option<R>,
     kind: ProcessSpawnOptionsStdioKind,
+    mut stop: oneshot::Receiver<()>,
 ) -> LuaResult<Vec<u8>>
 where
     R: AsyncRead + Unpin,
@@ -31,8 +91,17 @@ where
                 read_from.expect("read_from must be Some when stdio kind is Default");
 
             let mut buffer = Vec::new();
+            let mut chunk = vec![0; 8192];
 
-            read_from.read_to_end(&mut buffer).await.into_lua_err()?;
+            loop {
+                tokio::select! {
+                    res = read_from.read(&mut chunk) => match res.into_lua_err()? {
+                        0 => break,
+                        n => buffer.extend_from_slice(&chunk[..n]),
+                    },
+                    _ = &mut stop => break,
+                }
+            }
 
             buffer
         }
@@ -42,33 +111,149 @@ where
 
             let mut stdout = io::stdout();
             let mut tee = AsyncTeeWriter::new(&mut stdout);
+            let mut chunk = vec![0; 8192];
 
-            io::copy(&mut read_from, &mut tee).await.into_lua_err()?;
+            loop {
+                tokio::select! {
+                    res = read_from.read(&mut chunk) => match res.into_lua_err()? {
+                        0 => break,
+                        n => tee.write_all(&chunk[..n]).await.into_lua_err()?,
+                    },
+                    _ = &mut stop => break,
+                }
+            }
 
             tee.into_vec()
         }
     })
 }
 
+/**
+    Makes the given command start as the leader of its own process group if
+    it has a timeout, so that once it times out, any processes it spawned, such
+    as the ones started by a shell, can be terminated together with it.
+
+    Note that a child process in its own process group will not receive
+    signals sent to the foreground process group by the terminal, such as
+    the `SIGINT` sent when pressing Ctrl+C, and does nothing on windows.
+*/
+pub(super) fn use_own_process_group(
+    cmd: &mut Command,
+    timeout: Option<ProcessSpawnOptionsTimeout>,
+) -> &mut Command {
+    #[cfg(unix)]
+    if timeout.is_some() {
+        // SAFETY: Only async-signal-safe functions are called in the closure
+        unsafe {
+            cmd.pre_exec(|| match libc::setpgid(0, 0) {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = timeout;
+    cmd
+}
+
+async fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Option<ProcessSpawnOptionsTimeout>,
+    started: Instant,
+) -> LuaResult<(ExitStatus, ProcessUsage, bool)> {
+    let Some(timeout) = timeout else {
+        let (status, usage) = wait_with_usage(child, started).await.into_lua_err()?;
+        return Ok((status, usage, false));
+    };
+
+    if let Ok(res) = time::timeout(timeout.duration, wait_with_usage(child, started)).await {
+        let (status, usage) = res.into_lua_err()?;
+        return Ok((status, usage, false));
+    }
+
+    // NOTE: The child is the leader of its own process group, and we signal the
+    // whole group so that any processes spawned by the child are terminated too
+    let pgid = child.id();
+    timeout
+        .signal
+        .send_to_child_group(child, pgid)
+        .into_lua_err()?;
+
+    // NOTE: The child may ignore the signal we sent, so we always
+    // kill it once the grace period has passed, which it can not ignore
+    let res = match time::timeout(timeout.grace_period, wait_with_usage(child, started)).await {
+        Ok(res) => res,
+        Err(_) => {
+            ProcessSignal::Kill
+                .send_to_child_group(child, pgid)
+                .into_lua_err()?;
+            wait_with_usage(child, started).await
+        }
+    };
+
+    // Processes spawned by the child may still be running even
+    // if the child itself exited, and those should not outlive it
+    ProcessSignal::Kill
+        .send_to_child_group(child, pgid)
+        .into_lua_err()?;
+
+    let (status, usage) = res.into_lua_err()?;
+    Ok((status, usage, true))
+}
+
+async fn join_output(
+    mut task: JoinHandle<LuaResult<Vec<u8>>>,
+    stop: oneshot::Sender<()>,
+    timed_out: bool,
+) -> LuaResult<Vec<u8>> {
+    if !timed_out {
+        return task.await.into_lua_err()?;
+    }
+    if let Ok(res) = time::timeout(OUTPUT_DRAIN_TIMEOUT, &mut task).await {
+        return res.into_lua_err()?;
+    }
+    // NOTE: The reader may have already finished, in which
+    // case there is nobody to receive this, which is fine
+    stop.send(()).ok();
+    task.await.into_lua_err()?
+}
+
 pub(super) async fn wait_for_child(
     mut child: Child,
     stdout_kind: ProcessSpawnOptionsStdioKind,
     stderr_kind: ProcessSpawnOptionsStdioKind,
+    timeout: Option<ProcessSpawnOptionsTimeout>,
+    started: Instant,
 ) -> LuaResult<WaitForChildResult> {
+    let pid = child.id();
     let stdout_opt = child.stdout.take();
     let stderr_opt = child.stderr.take();
 
-    let stdout_task = task::spawn(read_with_stdio_kind(stdout_opt, stdout_kind));
-    let stderr_task = task::spawn(read_with_stdio_kind(stderr_opt, stderr_kind));
+    let (stdout_stop, stdout_stopped) = oneshot::channel();
+    let (stderr_stop, stderr_stopped) = oneshot::channel();
+
+    let stdout_task = task::spawn(read_with_stdio_kind(
+        stdout_opt,
+        stdout_kind,
+        stdout_stopped,
+    ));
+    let stderr_task = task::spawn(read_with_stdio_kind(
+        stderr_opt,
+        stderr_kind,
+        stderr_stopped,
+    ));
 
-    let status = child.wait().await.expect("Child process failed to start");
+    let (status, usage, timed_out) = wait_with_timeout(&mut child, timeout, started).await?;
 
-    let stdout_buffer = stdout_task.await.into_lua_err()??;
-    let stderr_buffer = stderr_task.await.into_lua_err()??;
+    let stdout_buffer = join_output(stdout_task, stdout_stop, timed_out).await?;
+    let stderr_buffer = join_output(stderr_task, stderr_stop, timed_out).await?;
 
     Ok(WaitForChildResult {
+        pid,
         status,
         stdout: stdout_buffer,
         stderr: stderr_buffer,
+        timed_out,
+        usage,
     })
 }
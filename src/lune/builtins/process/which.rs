@@ -0,0 +1,66 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+};
+
+use mlua::prelude::*;
+
+pub fn process_which(_: &Lua, name: String) -> LuaResult<Option<String>> {
+    Ok(find_executable(&name).map(|path| path.to_string_lossy().to_string()))
+}
+
+/**
+    Finds the full path to an executable, the same way that spawning a child
+    process would, by searching each of the directories in the `PATH` variable.
+
+    Names containing a path separator are not searched for, and are instead
+    resolved relative to the current working directory, also same as spawning.
+*/
+fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let cwd = env::current_dir().ok()?;
+    if name.contains('/') || name.contains(MAIN_SEPARATOR) {
+        return candidates(&cwd.join(name)).find(|path| is_executable(path));
+    }
+
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .flat_map(|dir| candidates(&cwd.join(dir).join(name)).collect::<Vec<_>>())
+        .find(|path| is_executable(path))
+}
+
+/**
+    Gets all paths that an executable may be found at, which on Windows
+    also includes the path with each extension listed in `PATHEXT` added.
+*/
+fn candidates(path: &Path) -> impl Iterator<Item = PathBuf> {
+    let mut paths = vec![path.to_path_buf()];
+    if cfg!(windows) {
+        let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        for ext in extensions.split(';').filter(|ext| !ext.is_empty()) {
+            let mut with_ext = OsString::from(path.as_os_str());
+            with_ext.push(ext);
+            paths.push(PathBuf::from(with_ext));
+        }
+    }
+    paths.into_iter()
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
@@ -1,29 +1,67 @@
 use mlua::prelude::*;
 
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
-use tokio::{
-    io::{self, AsyncWriteExt},
-    task,
+use console::Term;
+use dialoguer::{
+    theme::{ColorfulTheme, Theme},
+    Confirm, Editor, FuzzySelect, Input, MultiSelect, Password, Select,
 };
+use tokio::task;
 
-use crate::lune::util::{
-    formatting::{
-        format_style, pretty_format_multi_value, style_from_color_str, style_from_style_str,
+use crate::lune::{
+    scheduler::Scheduler,
+    util::{
+        formatting::{
+            format_style, pretty_format_multi_value, style_from_color_str, style_from_style_str,
+            FormatOptions,
+        },
+        progress::{write_stderr, write_stdout},
+        TableBuilder,
     },
-    TableBuilder,
 };
 
+mod progress;
+use progress::{stdio_progress, stdio_spinner};
+
 mod prompt;
 use prompt::{PromptKind, PromptOptions, PromptResult};
 
+mod terminal;
+use terminal::{stdio_is_terminal, stdio_read_event, stdio_set_raw_mode, stdio_size};
+
+pub use terminal::restore_terminal_mode;
+
+mod stdin;
+use stdin::{stdio_lines, stdio_read, stdio_read_line, stdio_read_to_end};
+
+/*
+    Validators are called in protected mode, so that any errors they throw
+    are shown to the user as validation errors, instead of being reported
+    as unhandled errors by the scheduler
+*/
+const PROTECTED_VALIDATOR_LUA: &str = r#"
+return pcall(...)
+"#;
+
 pub fn create(lua: &'static Lua) -> LuaResult<LuaTable<'_>> {
     TableBuilder::new(lua)?
         .with_function("color", stdio_color)?
         .with_function("style", stdio_style)?
         .with_function("format", stdio_format)?
+        .with_function("formatWith", stdio_format_with)?
+        .with_function("setFormatDefaults", stdio_set_format_defaults)?
         .with_async_function("write", stdio_write)?
         .with_async_function("ewrite", stdio_ewrite)?
         .with_async_function("prompt", stdio_prompt)?
+        .with_async_function("read", stdio_read)?
+        .with_async_function("readLine", stdio_read_line)?
+        .with_async_function("readToEnd", stdio_read_to_end)?
+        .with_function("lines", stdio_lines)?
+        .with_function("progress", stdio_progress)?
+        .with_function("spinner", stdio_spinner)?
+        .with_function("isTerminal", stdio_is_terminal)?
+        .with_function("size", stdio_size)?
+        .with_function("setRawMode", stdio_set_raw_mode)?
+        .with_async_function("readEvent", stdio_read_event)?
         .build_readonly()
 }
 
@@ -37,42 +75,133 @@ fn stdio_style(_: &Lua, color: String) -> LuaResult<String> {
     Ok(ansi_string)
 }
 
-fn stdio_format(_: &Lua, args: LuaMultiValue) -> LuaResult<String> {
-    pretty_format_multi_value(&args)
+fn stdio_format(lua: &Lua, args: LuaMultiValue) -> LuaResult<String> {
+    pretty_format_multi_value(&args, &FormatOptions::from_app_data(lua))
+}
+
+fn stdio_format_with(lua: &Lua, (options, args): (LuaTable, LuaMultiValue)) -> LuaResult<String> {
+    let options = FormatOptions::from_app_data(lua).with_table(&options)?;
+    pretty_format_multi_value(&args, &options)
+}
+
+fn stdio_set_format_defaults(lua: &Lua, options: LuaTable) -> LuaResult<()> {
+    let options = FormatOptions::from_app_data(lua).with_table(&options)?;
+    // NOTE: Other app data may be borrowed for as long as a thread is waiting, such as
+    // the scheduler during task.delay, so we can only replace the existing value here
+    let mut defaults = lua
+        .app_data_mut::<FormatOptions>()
+        .expect("Lua struct is missing format options");
+    *defaults = options;
+    Ok(())
 }
 
 async fn stdio_write(_: &Lua, s: LuaString<'_>) -> LuaResult<()> {
-    let mut stdout = io::stdout();
-    stdout.write_all(s.as_bytes()).await?;
-    stdout.flush().await?;
+    write_stdout(s.as_bytes()).await?;
     Ok(())
 }
 
 async fn stdio_ewrite(_: &Lua, s: LuaString<'_>) -> LuaResult<()> {
-    let mut stderr = io::stderr();
-    stderr.write_all(s.as_bytes()).await?;
-    stderr.flush().await?;
+    write_stderr(s.as_bytes()).await?;
     Ok(())
 }
 
-async fn stdio_prompt(_: &Lua, options: PromptOptions) -> LuaResult<PromptResult> {
-    task::spawn_blocking(move || prompt(options))
-        .await
-        .into_lua_err()?
+async fn stdio_prompt(lua: &Lua, mut options: PromptOptions) -> LuaResult<PromptResult> {
+    let Some(validator) = options.validator.take() else {
+        return task::spawn_blocking(move || prompt(options))
+            .await
+            .into_lua_err()?;
+    };
+    // NOTE: Validators are lua functions, so they can not be called from the blocking
+    // thread that the prompt runs on, instead we call them here after the user has given
+    // their input, and prompt again with the validation error until the input is valid
+    let validator = lua.registry_value::<LuaFunction>(&validator)?;
+    let text = options.text.unwrap_or_default();
+    let mut input = options.default_string.unwrap_or_default();
+    let mut error = None;
+    loop {
+        let (text, initial) = (text.clone(), input);
+        input = task::spawn_blocking(move || prompt_text(&text, &initial, error.as_deref()))
+            .await
+            .into_lua_err()??;
+        error = match validate_input(lua, validator.clone(), &input).await? {
+            None => return Ok(PromptResult::String(input)),
+            Some(message) => Some(message),
+        };
+    }
+}
+
+/**
+    Calls the given validator function in a new protected thread, so that it may yield,
+    and returns the validation error message for the given input, if it was not valid.
+*/
+async fn validate_input(
+    lua: &Lua,
+    validator: LuaFunction<'_>,
+    input: &str,
+) -> LuaResult<Option<String>> {
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    let protected = lua
+        .load(PROTECTED_VALIDATOR_LUA)
+        .set_name("stdio.prompt.validator")
+        .into_function()?;
+    let thread_id = sched.push_back(lua, protected, (validator, input))?;
+    let mut values = sched
+        .park(sched.wait_for_thread(lua, thread_id))
+        .await?
+        .into_iter();
+    let ok = values.next().and_then(|v| v.as_boolean()) == Some(true);
+    Ok(match (ok, values.next()) {
+        (true, None | Some(LuaValue::Nil | LuaValue::Boolean(true))) => None,
+        (_, Some(LuaValue::String(message))) => Some(message.to_string_lossy().to_string()),
+        (false, Some(e)) => Some(e.to_string()?),
+        _ => Some("Invalid input".to_string()),
+    })
+}
+
+fn prompt_text(text: &str, initial: &str, error: Option<&str>) -> LuaResult<String> {
+    let theme = ColorfulTheme::default();
+    if let Some(error) = error {
+        let mut message = String::new();
+        theme.format_error(&mut message, error).into_lua_err()?;
+        Term::stderr().write_line(&message).into_lua_err()?;
+    }
+    Input::with_theme(&theme)
+        .allow_empty(true)
+        .with_prompt(text)
+        .with_initial_text(initial)
+        .interact_text()
+        .into_lua_err()
 }
 
 fn prompt(options: PromptOptions) -> LuaResult<PromptResult> {
     let theme = ColorfulTheme::default();
     match options.kind {
         PromptKind::Text => {
-            let input: String = Input::with_theme(&theme)
-                .allow_empty(true)
-                .with_prompt(options.text.unwrap_or_default())
-                .with_initial_text(options.default_string.unwrap_or_default())
-                .interact_text()
-                .into_lua_err()?;
+            let input = prompt_text(
+                &options.text.unwrap_or_default(),
+                &options.default_string.unwrap_or_default(),
+                None,
+            )?;
             Ok(PromptResult::String(input))
         }
+        PromptKind::Password => {
+            let mut prompt =
+                Password::with_theme(&theme).with_prompt(options.text.unwrap_or_default());
+            // A string or true given as the default value means
+            // that the user should confirm the password they typed
+            let confirmation = match (options.default_string, options.default_bool) {
+                (Some(s), _) => Some(s),
+                (None, Some(true)) => Some("Confirm password".to_string()),
+                (None, _) => None,
+            };
+            if let Some(confirmation) = confirmation {
+                prompt = prompt.with_confirmation(confirmation, "Passwords do not match");
+            }
+            let password = prompt.interact().into_lua_err()?;
+            Ok(PromptResult::String(password))
+        }
         PromptKind::Confirm => {
             let mut prompt = Confirm::with_theme(&theme);
             if let Some(b) = options.default_bool {
@@ -85,20 +214,41 @@ fn prompt(options: PromptOptions) -> LuaResult<PromptResult> {
             Ok(PromptResult::Boolean(result))
         }
         PromptKind::Select => {
-            let chosen = Select::with_theme(&theme)
-                .with_prompt(&options.text.unwrap_or_default())
-                .items(&options.options.expect("Missing options in prompt options"))
-                .interact_opt()
-                .into_lua_err()?;
+            let mut prompt = Select::with_theme(&theme)
+                .with_prompt(options.text.unwrap_or_default())
+                .items(&options.options.expect("Missing options in prompt options"));
+            if let Some(idx) = options.default_index {
+                prompt = prompt.default(idx - 1);
+            }
+            let chosen = prompt.interact_opt().into_lua_err()?;
+            Ok(match chosen {
+                Some(idx) => PromptResult::Index(idx + 1),
+                None => PromptResult::None,
+            })
+        }
+        PromptKind::FuzzySelect => {
+            let mut prompt = FuzzySelect::with_theme(&theme)
+                .with_prompt(options.text.unwrap_or_default())
+                .items(&options.options.expect("Missing options in prompt options"));
+            if let Some(idx) = options.default_index {
+                prompt = prompt.default(idx - 1);
+            }
+            let chosen = prompt.interact_opt().into_lua_err()?;
             Ok(match chosen {
                 Some(idx) => PromptResult::Index(idx + 1),
                 None => PromptResult::None,
             })
         }
         PromptKind::MultiSelect => {
+            let items = options.options.expect("Missing options in prompt options");
+            let defaults = options.default_indices.unwrap_or_default();
+            let defaults = (1..=items.len())
+                .map(|idx| defaults.contains(&idx))
+                .collect::<Vec<_>>();
             let chosen = MultiSelect::with_theme(&theme)
                 .with_prompt(&options.text.unwrap_or_default())
-                .items(&options.options.expect("Missing options in prompt options"))
+                .items(&items)
+                .defaults(&defaults)
                 .interact_opt()
                 .into_lua_err()?;
             Ok(match chosen {
@@ -108,5 +258,14 @@ fn prompt(options: PromptOptions) -> LuaResult<PromptResult> {
                 }
             })
         }
+        PromptKind::Editor => {
+            let edited = Editor::new()
+                .edit(&options.default_string.unwrap_or_default())
+                .into_lua_err()?;
+            Ok(match edited {
+                Some(text) => PromptResult::String(text),
+                None => PromptResult::None,
+            })
+        }
     }
 }
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use mlua::prelude::*;
+
+use crate::lune::util::{progress::progress_bars, TableBuilder};
+
+const SPINNER_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+const TEMPLATE_BAR: &str = "[{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}";
+const TEMPLATE_BAR_BYTES: &str =
+    "[{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}";
+const TEMPLATE_COUNTER: &str = "{spinner:.green} {pos} {msg}";
+const TEMPLATE_COUNTER_BYTES: &str = "{spinner:.green} {bytes} ({bytes_per_sec}) {msg}";
+const TEMPLATE_SPINNER: &str = "{spinner:.green} {msg}";
+
+#[derive(Debug, Clone, Default)]
+pub struct ProgressOptions {
+    pub total: Option<u64>,
+    pub message: Option<String>,
+    pub bytes: bool,
+    pub clear: bool,
+}
+
+impl<'lua> FromLua<'lua> for ProgressOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let options = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            value => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "ProgressOptions",
+                    message: Some(format!(
+                        "Invalid progress options - expected table or nil, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+        Ok(Self {
+            total: options.get("total")?,
+            message: options.get("message")?,
+            bytes: options.get::<_, Option<bool>>("bytes")?.unwrap_or_default(),
+            clear: options.get::<_, Option<bool>>("clear")?.unwrap_or_default(),
+        })
+    }
+}
+
+impl ProgressOptions {
+    fn style(&self) -> ProgressStyle {
+        let template = match (self.total.is_some(), self.bytes) {
+            (true, false) => TEMPLATE_BAR,
+            (true, true) => TEMPLATE_BAR_BYTES,
+            (false, false) => TEMPLATE_COUNTER,
+            (false, true) => TEMPLATE_COUNTER_BYTES,
+        };
+        ProgressStyle::with_template(template)
+            .expect("Progress bar template is valid")
+            .progress_chars("=> ")
+    }
+}
+
+pub fn stdio_progress(lua: &'static Lua, options: ProgressOptions) -> LuaResult<LuaTable<'static>> {
+    let bar = match options.total {
+        Some(total) => ProgressBar::new(total),
+        None => ProgressBar::no_length(),
+    };
+    let bar = progress_bars().add(bar.with_style(options.style()));
+    if let Some(message) = options.message {
+        bar.set_message(message);
+    }
+    if options.total.is_none() {
+        bar.enable_steady_tick(SPINNER_TICK_INTERVAL);
+    }
+    create_progress_object(lua, bar, options.clear)
+}
+
+pub fn stdio_spinner(lua: &'static Lua, options: ProgressOptions) -> LuaResult<LuaTable<'static>> {
+    let style = ProgressStyle::with_template(TEMPLATE_SPINNER).expect("Spinner template is valid");
+    let bar = progress_bars().add(ProgressBar::new_spinner().with_style(style));
+    if let Some(message) = options.message {
+        bar.set_message(message);
+    }
+    bar.enable_steady_tick(SPINNER_TICK_INTERVAL);
+    create_progress_object(lua, bar, options.clear)
+}
+
+fn create_progress_object(
+    lua: &'static Lua,
+    bar: ProgressBar,
+    clear: bool,
+) -> LuaResult<LuaTable<'static>> {
+    let bar_set = bar.clone();
+    let bar_inc = bar.clone();
+    let bar_message = bar.clone();
+    let bar_finish = bar;
+    TableBuilder::new(lua)?
+        .with_function("set", move |_, position: u64| {
+            bar_set.set_position(position);
+            Ok(())
+        })?
+        .with_function("inc", move |_, delta: Option<u64>| {
+            bar_inc.inc(delta.unwrap_or(1));
+            Ok(())
+        })?
+        .with_function("message", move |_, message: String| {
+            bar_message.set_message(message);
+            Ok(())
+        })?
+        .with_function("finish", move |_, message: Option<String>| {
+            if bar_finish.is_finished() {
+                return Ok(());
+            }
+            if clear {
+                bar_finish.finish_and_clear();
+                progress_bars().remove(&bar_finish);
+            } else if let Some(message) = message {
+                bar_finish.finish_with_message(message);
+            } else {
+                bar_finish.finish();
+            }
+            Ok(())
+        })?
+        .build_readonly()
+}
@@ -5,14 +5,25 @@ use mlua::prelude::*;
 #[derive(Debug, Clone, Copy)]
 pub enum PromptKind {
     Text,
+    Password,
     Confirm,
     Select,
+    FuzzySelect,
     MultiSelect,
+    Editor,
 }
 
 impl PromptKind {
     fn get_all() -> Vec<Self> {
-        vec![Self::Text, Self::Confirm, Self::Select, Self::MultiSelect]
+        vec![
+            Self::Text,
+            Self::Password,
+            Self::Confirm,
+            Self::Select,
+            Self::FuzzySelect,
+            Self::MultiSelect,
+            Self::Editor,
+        ]
     }
 }
 
@@ -29,9 +40,12 @@ impl fmt::Display for PromptKind {
             "{}",
             match self {
                 Self::Text => "Text",
+                Self::Password => "Password",
                 Self::Confirm => "Confirm",
                 Self::Select => "Select",
+                Self::FuzzySelect => "FuzzySelect",
                 Self::MultiSelect => "MultiSelect",
+                Self::Editor => "Editor",
             }
         )
     }
@@ -66,9 +80,12 @@ impl<'lua> FromLua<'lua> for PromptKind {
             // show the user a descriptive error message
             match s.as_ref() {
                 "text" => Ok(Self::Text),
+                "password" => Ok(Self::Password),
                 "confirm" => Ok(Self::Confirm),
                 "select" => Ok(Self::Select),
+                "fuzzyselect" => Ok(Self::FuzzySelect),
                 "multiselect" => Ok(Self::MultiSelect),
+                "editor" => Ok(Self::Editor),
                 s => Err(LuaError::FromLuaConversionError {
                     from: "string",
                     to: "PromptKind",
@@ -98,6 +115,9 @@ pub struct PromptOptions {
     pub default_string: Option<String>,
     pub default_bool: Option<bool>,
     pub options: Option<Vec<String>>,
+    pub default_index: Option<usize>,
+    pub default_indices: Option<Vec<usize>>,
+    pub validator: Option<LuaRegistryKey>,
 }
 
 impl<'lua> FromLuaMulti<'lua> for PromptOptions {
@@ -111,8 +131,9 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
         // Argument #2 - prompt text (optional)
         let text = values
             .pop_front()
-            .map(|text| String::from_lua(text, lua))
-            .transpose()?;
+            .map(|text| Option::<String>::from_lua(text, lua))
+            .transpose()?
+            .flatten();
         // Argument #3 - default value / options,
         // this is different per each prompt kind
         let (default_bool, default_string, options) = match values.pop_front() {
@@ -139,11 +160,36 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
                 }
             },
         };
+        // Argument #4 - validator / default selection,
+        // this is also different per each prompt kind
+        let (validator, default_index, default_indices) = match (kind, values.pop_front()) {
+            (_, None | Some(LuaValue::Nil)) => (None, None, None),
+            (PromptKind::Text, Some(LuaValue::Function(f))) => {
+                (Some(lua.create_registry_value(f)?), None, None)
+            }
+            (PromptKind::Select | PromptKind::FuzzySelect, Some(value)) => {
+                (None, Some(usize::from_lua(value, lua)?), None)
+            }
+            (PromptKind::MultiSelect, Some(value)) => {
+                (None, None, Some(Vec::<usize>::from_lua(value, lua)?))
+            }
+            (kind, Some(value)) => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "PromptOptions",
+                    message: Some(match kind {
+                        PromptKind::Text => "Argument #4 must be a function or nil".to_string(),
+                        kind => format!("Argument #4 must be nil for prompt kind '{kind}'"),
+                    }),
+                })
+            }
+        };
         /*
             Make sure we got the required values for the specific prompt kind:
 
             - "Confirm" requires a message to be present so the user knows what they are confirming
-            - "Select" and "MultiSelect" both require a table of options to choose from
+            - "Select", "FuzzySelect" and "MultiSelect" all require a table of options to choose from
+            - Default selections for "Select", "FuzzySelect" and "MultiSelect" must be valid options
         */
         if matches!(kind, PromptKind::Confirm) && text.is_none() {
             return Err(LuaError::FromLuaConversionError {
@@ -152,13 +198,32 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
                 message: Some("Argument #2 missing or nil".to_string()),
             });
         }
-        if matches!(kind, PromptKind::Select | PromptKind::MultiSelect) && options.is_none() {
+        if matches!(
+            kind,
+            PromptKind::Select | PromptKind::FuzzySelect | PromptKind::MultiSelect
+        ) && options.is_none()
+        {
             return Err(LuaError::FromLuaConversionError {
                 from: "nil",
                 to: "PromptOptions",
                 message: Some("Argument #3 missing or nil".to_string()),
             });
         }
+        let option_count = options.as_ref().map_or(0, Vec::len);
+        let invalid_index = default_index
+            .iter()
+            .chain(default_indices.iter().flatten())
+            .find(|index| **index < 1 || **index > option_count);
+        if let Some(index) = invalid_index {
+            return Err(LuaError::FromLuaConversionError {
+                from: "number",
+                to: "PromptOptions",
+                message: Some(format!(
+                    "Argument #4 contains invalid default option {index}, \
+                    expected a number between 1 and {option_count}"
+                )),
+            });
+        }
         // All good, return the prompt options
         Ok(Self {
             kind,
@@ -166,6 +231,9 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
             default_bool,
             default_string,
             options,
+            default_index,
+            default_indices,
+            validator,
         })
     }
 }
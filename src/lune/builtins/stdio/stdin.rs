@@ -0,0 +1,159 @@
+use std::{
+    io::{self, Read},
+    pin::Pin,
+    task::{Context, Poll},
+    thread,
+};
+
+use mlua::prelude::*;
+use once_cell::sync::Lazy;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader, ReadBuf},
+    sync::{mpsc, Mutex as AsyncMutex},
+};
+
+const STDIN_CHUNK_SIZE: usize = 8192;
+
+/**
+    The buffered stdin reader that is shared by all stdio functions.
+
+    Stdin is global to the process, so if there were several readers,
+    any one of them could buffer input that another was trying to read.
+*/
+static STDIN: Lazy<AsyncMutex<BufReader<StdinReader>>> =
+    Lazy::new(|| AsyncMutex::new(BufReader::new(StdinReader::spawn())));
+
+/**
+    An async reader for stdin.
+
+    Reads from stdin are blocking and can not be cancelled, so they happen on
+    a separate thread that is never joined, unlike [`tokio::io::stdin`] which
+    would make the runtime wait for any pending read before shutting down.
+*/
+struct StdinReader {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    offset: usize,
+}
+
+impl StdinReader {
+    fn spawn() -> Self {
+        // NOTE: We only ever read one chunk ahead of what has been consumed,
+        // any child processes that inherit stdin will get the rest of it
+        let (tx, rx) = mpsc::channel(1);
+        thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            loop {
+                let mut chunk = vec![0; STDIN_CHUNK_SIZE];
+                let res = match stdin.read(&mut chunk) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Ok(chunk)
+                    }
+                };
+                let done = !matches!(&res, Ok(chunk) if !chunk.is_empty());
+                if tx.blocking_send(res).is_err() || done {
+                    break;
+                }
+            }
+        });
+        Self {
+            rx,
+            chunk: Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+impl AsyncRead for StdinReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.offset >= self.chunk.len() {
+            match self.rx.poll_recv(cx) {
+                Poll::Pending => return Poll::Pending,
+                // The reader thread has stopped, meaning we reached the end of input
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.offset = 0;
+                }
+            }
+        }
+        let len = buf.remaining().min(self.chunk.len() - self.offset);
+        let offset = self.offset;
+        buf.put_slice(&self.chunk[offset..offset + len]);
+        self.offset += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub async fn stdio_read(lua: &Lua, max_bytes: Option<usize>) -> LuaResult<Option<LuaString<'_>>> {
+    let mut stdin = STDIN.lock().await;
+    let buf = stdin.fill_buf().await.into_lua_err()?;
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let len = max_bytes.unwrap_or(buf.len()).min(buf.len());
+    let bytes = lua.create_string(&buf[..len])?;
+    stdin.consume(len);
+    Ok(Some(bytes))
+}
+
+async fn read_line(stdin: &mut BufReader<StdinReader>) -> LuaResult<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if stdin.read_until(b'\n', &mut line).await.into_lua_err()? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+pub async fn stdio_read_line(lua: &Lua, _: ()) -> LuaResult<Option<LuaString<'_>>> {
+    let mut stdin = STDIN.lock().await;
+    match read_line(&mut stdin).await? {
+        Some(line) => Ok(Some(lua.create_string(line)?)),
+        None => Ok(None),
+    }
+}
+
+/**
+    Creates an iterator function over lines read from stdin.
+
+    Luau can not yield from inside of a generic for loop, so unlike the other
+    functions for reading from stdin, this iterator waits for lines synchronously.
+
+    The line is still read using the async reader, but it is driven by a local
+    executor instead of the tokio runtime. The reader only depends on the stdin
+    thread to make progress, so unlike `block_in_place`, this also works when
+    running on a current thread runtime.
+*/
+pub fn stdio_lines(lua: &Lua, _: ()) -> LuaResult<LuaFunction<'_>> {
+    lua.create_function(|lua, _: LuaMultiValue| {
+        let mut stdin = STDIN.try_lock().map_err(|_| {
+            LuaError::runtime("Failed to read line - stdin is already being read from")
+        })?;
+        let line = futures_executor::block_on(read_line(&mut stdin))?;
+        match line {
+            Some(line) => Ok(Some(lua.create_string(line)?)),
+            None => Ok(None),
+        }
+    })
+}
+
+pub async fn stdio_read_to_end(lua: &Lua, _: ()) -> LuaResult<LuaString<'_>> {
+    let mut bytes = Vec::new();
+    let mut stdin = STDIN.lock().await;
+    stdin.read_to_end(&mut bytes).await.into_lua_err()?;
+    lua.create_string(bytes)
+}
@@ -0,0 +1,211 @@
+use std::io::{self, IsTerminal};
+
+use crossterm::{
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use futures_util::StreamExt;
+use mlua::prelude::*;
+
+use crate::lune::util::TableBuilder;
+
+#[derive(Debug, Clone, Copy)]
+pub enum TerminalStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl<'lua> FromLua<'lua> for TerminalStream {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        if let LuaValue::String(s) = &value {
+            match s.to_str()? {
+                "stdin" => return Ok(Self::Stdin),
+                "stdout" => return Ok(Self::Stdout),
+                "stderr" => return Ok(Self::Stderr),
+                _ => {}
+            }
+        }
+        Err(LuaError::FromLuaConversionError {
+            from: value.type_name(),
+            to: "TerminalStream",
+            message: Some(format!(
+                "Invalid stream '{}' - expected one of 'stdin', 'stdout', 'stderr'",
+                value.to_string()?
+            )),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TerminalEvent {
+    Key {
+        key: String,
+        ctrl: bool,
+        alt: bool,
+        shift: bool,
+    },
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+}
+
+impl TerminalEvent {
+    fn from_event(event: Event) -> Option<Self> {
+        match event {
+            Event::Key(key) => Self::from_key_event(key),
+            Event::Resize(cols, rows) => Some(Self::Resize { cols, rows }),
+            _ => None,
+        }
+    }
+
+    fn from_key_event(event: KeyEvent) -> Option<Self> {
+        // NOTE: Key release events are only ever sent on Windows
+        if event.kind == KeyEventKind::Release {
+            return None;
+        }
+        let mut shift = event.modifiers.contains(KeyModifiers::SHIFT);
+        let key = match event.code {
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("f{n}"),
+            KeyCode::Up => "up".to_string(),
+            KeyCode::Down => "down".to_string(),
+            KeyCode::Left => "left".to_string(),
+            KeyCode::Right => "right".to_string(),
+            KeyCode::Home => "home".to_string(),
+            KeyCode::End => "end".to_string(),
+            KeyCode::PageUp => "pageUp".to_string(),
+            KeyCode::PageDown => "pageDown".to_string(),
+            KeyCode::Enter => "enter".to_string(),
+            KeyCode::Tab => "tab".to_string(),
+            KeyCode::BackTab => {
+                shift = true;
+                "tab".to_string()
+            }
+            KeyCode::Backspace => "backspace".to_string(),
+            KeyCode::Delete => "delete".to_string(),
+            KeyCode::Insert => "insert".to_string(),
+            KeyCode::Esc => "escape".to_string(),
+            _ => return None,
+        };
+        Some(Self::Key {
+            key,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+            alt: event.modifiers.contains(KeyModifiers::ALT),
+            shift,
+        })
+    }
+
+    fn into_lua_table(self, lua: &Lua) -> LuaResult<LuaTable<'_>> {
+        match self {
+            Self::Key {
+                key,
+                ctrl,
+                alt,
+                shift,
+            } => TableBuilder::new(lua)?
+                .with_value("kind", "key")?
+                .with_value("key", key)?
+                .with_value("ctrl", ctrl)?
+                .with_value("alt", alt)?
+                .with_value("shift", shift)?
+                .build_readonly(),
+            Self::Resize { cols, rows } => TableBuilder::new(lua)?
+                .with_value("kind", "resize")?
+                .with_value("cols", cols)?
+                .with_value("rows", rows)?
+                .build_readonly(),
+        }
+    }
+}
+
+/**
+    Disables raw mode for the terminal again once it is dropped.
+*/
+struct RawModeGuard;
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+/**
+    Disables raw mode for the terminal, if it was enabled by a script.
+
+    This should be called once a script has finished running, so that the
+    terminal is not left in raw mode if the script did not disable it itself.
+*/
+pub fn restore_terminal_mode() {
+    // NOTE: This only checks if raw mode was enabled by us, not by some other process
+    if terminal::is_raw_mode_enabled().unwrap_or_default() {
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+pub fn stdio_is_terminal(_: &Lua, stream: TerminalStream) -> LuaResult<bool> {
+    Ok(match stream {
+        TerminalStream::Stdin => io::stdin().is_terminal(),
+        TerminalStream::Stdout => io::stdout().is_terminal(),
+        TerminalStream::Stderr => io::stderr().is_terminal(),
+    })
+}
+
+pub fn stdio_size(lua: &Lua, _: ()) -> LuaResult<Option<LuaTable<'_>>> {
+    match terminal::size() {
+        Err(_) => Ok(None),
+        Ok((cols, rows)) => Ok(Some(
+            TableBuilder::new(lua)?
+                .with_value("cols", cols)?
+                .with_value("rows", rows)?
+                .build_readonly()?,
+        )),
+    }
+}
+
+pub fn stdio_set_raw_mode(_: &Lua, enabled: bool) -> LuaResult<()> {
+    if enabled {
+        terminal::enable_raw_mode()
+            .map_err(|e| LuaError::RuntimeError(format!("Failed to enable raw mode - {e}")))?;
+    } else {
+        terminal::disable_raw_mode()
+            .map_err(|e| LuaError::RuntimeError(format!("Failed to disable raw mode - {e}")))?;
+    }
+    Ok(())
+}
+
+pub async fn stdio_read_event(lua: &Lua, _: ()) -> LuaResult<LuaTable<'_>> {
+    // Key events are only sent as keys are pressed when in raw mode,
+    // so we temporarily enable it if the script has not done so already
+    let _guard = match terminal::is_raw_mode_enabled().into_lua_err()? {
+        true => None,
+        false => {
+            terminal::enable_raw_mode().map_err(|e| {
+                LuaError::RuntimeError(format!("Failed to read terminal event - {e}"))
+            })?;
+            Some(RawModeGuard)
+        }
+    };
+
+    let mut events = EventStream::new();
+    loop {
+        match events.next().await {
+            None => {
+                return Err(LuaError::runtime(
+                    "Failed to read terminal event - no terminal",
+                ))
+            }
+            Some(Err(e)) => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Failed to read terminal event - {e}"
+                )))
+            }
+            Some(Ok(event)) => {
+                if let Some(event) = TerminalEvent::from_event(event) {
+                    return event.into_lua_table(lua);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use mlua::prelude::*;
+use tokio::sync::Notify;
+
+use crate::lune::{scheduler::Scheduler, util::TableBuilder};
+
+/**
+    Shared state for a channel, values are stored in the
+    lua registry while they are waiting to be received.
+*/
+#[derive(Debug, Default)]
+struct ChannelState {
+    queue: VecDeque<LuaRegistryKey>,
+    capacity: Option<usize>,
+    closed: bool,
+}
+
+impl ChannelState {
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.queue.len() >= capacity)
+    }
+}
+
+/**
+    A channel that can be used to send values between lua threads.
+
+    Threads waiting to send or receive are parked on their corresponding
+    [`Notify`] and only resumed by the scheduler once they have been woken up.
+
+    Since only other lua threads can wake them up, waiting threads are also
+    parked in the scheduler, which lets it detect when they would wait forever.
+*/
+#[derive(Debug, Clone, Default)]
+struct Channel {
+    state: Rc<RefCell<ChannelState>>,
+    sendable: Rc<Notify>,
+    receivable: Rc<Notify>,
+}
+
+impl Channel {
+    fn new(capacity: Option<usize>) -> Self {
+        let channel = Self::default();
+        channel.state.borrow_mut().capacity = capacity;
+        channel
+    }
+
+    async fn send(&self, lua: &Lua, value: LuaValue<'_>) -> LuaResult<()> {
+        let sched = *lua
+            .app_data_ref::<&Scheduler>()
+            .expect("Lua struct is missing scheduler");
+        loop {
+            {
+                let mut state = self.state.borrow_mut();
+                if state.closed {
+                    return Err(LuaError::runtime(
+                        "Failed to send value - channel is closed",
+                    ));
+                }
+                if !state.is_full() {
+                    state.queue.push_back(lua.create_registry_value(value)?);
+                    self.receivable.notify_one();
+                    return Ok(());
+                }
+            }
+            sched.park(self.sendable.notified()).await;
+        }
+    }
+
+    fn try_send(&self, lua: &Lua, value: LuaValue) -> LuaResult<bool> {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return Err(LuaError::runtime(
+                "Failed to send value - channel is closed",
+            ));
+        }
+        if state.is_full() {
+            return Ok(false);
+        }
+        state.queue.push_back(lua.create_registry_value(value)?);
+        self.receivable.notify_one();
+        Ok(true)
+    }
+
+    async fn recv<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let sched = *lua
+            .app_data_ref::<&Scheduler>()
+            .expect("Lua struct is missing scheduler");
+        loop {
+            if let Some(value) = self.try_recv(lua)? {
+                return Ok(value);
+            }
+            if self.state.borrow().closed {
+                return Ok(LuaValue::Nil);
+            }
+            sched.park(self.receivable.notified()).await;
+        }
+    }
+
+    fn try_recv<'lua>(&self, lua: &'lua Lua) -> LuaResult<Option<LuaValue<'lua>>> {
+        let key = self.state.borrow_mut().queue.pop_front();
+        match key {
+            None => Ok(None),
+            Some(key) => {
+                let value = lua.registry_value(&key)?;
+                lua.remove_registry_value(key)?;
+                self.sendable.notify_one();
+                Ok(Some(value))
+            }
+        }
+    }
+
+    fn close(&self) {
+        self.state.borrow_mut().closed = true;
+        // NOTE: Any threads waiting to send should error, and
+        // any threads waiting to receive should get nil, once
+        // all of the values that were already sent are received
+        self.sendable.notify_waiters();
+        self.receivable.notify_waiters();
+    }
+}
+
+pub fn task_channel(lua: &'static Lua, capacity: Option<usize>) -> LuaResult<LuaTable<'static>> {
+    if capacity == Some(0) {
+        return Err(LuaError::runtime(
+            "Channel capacity must be at least 1, or nil for an unbounded channel",
+        ));
+    }
+
+    let channel = Channel::new(capacity);
+    let channel_send = channel.clone();
+    let channel_try_send = channel.clone();
+    let channel_recv = channel.clone();
+    let channel_try_recv = channel.clone();
+    let channel_close = channel.clone();
+    let channel_is_closed = channel.clone();
+    let channel_len = channel;
+
+    TableBuilder::new(lua)?
+        .with_async_function("send", move |lua, value: LuaValue| {
+            let channel = channel_send.clone();
+            async move { channel.send(lua, value).await }
+        })?
+        .with_function("trySend", move |lua, value: LuaValue| {
+            channel_try_send.try_send(lua, value)
+        })?
+        .with_async_function("recv", move |lua, _: ()| {
+            let channel = channel_recv.clone();
+            async move { channel.recv(lua).await }
+        })?
+        .with_function("tryRecv", move |lua, _: ()| channel_try_recv.try_recv(lua))?
+        .with_function("close", move |_, _: ()| {
+            channel_close.close();
+            Ok(())
+        })?
+        .with_function("isClosed", move |_, _: ()| {
+            Ok(channel_is_closed.state.borrow().closed)
+        })?
+        .with_function("len", move |_, _: ()| {
+            Ok(channel_len.state.borrow().queue.len())
+        })?
+        .build_readonly()
+}
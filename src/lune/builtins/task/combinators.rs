@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use futures_util::future::{join_all, select_all};
+use mlua::prelude::*;
+use tokio::time;
+
+use crate::lune::{
+    scheduler::{Scheduler, SchedulerThreadId},
+    util::TableBuilder,
+};
+
+/*
+    Functions given to the combinators are called in protected mode,
+    so that any errors they throw become part of their result table,
+    and are not reported as unhandled errors by the scheduler
+*/
+const PROTECTED_IMPL_LUA: &str = r#"
+return pcall(...)
+"#;
+
+/**
+    Spawns the given function in a new protected thread, which will be resumed by the scheduler.
+*/
+fn spawn_protected<'lua>(
+    lua: &'lua Lua,
+    sched: &Scheduler,
+    func: LuaFunction<'lua>,
+    mut args: LuaMultiValue<'lua>,
+) -> LuaResult<(LuaThread<'lua>, SchedulerThreadId)> {
+    let protected = lua
+        .load(PROTECTED_IMPL_LUA)
+        .set_name("task.protected")
+        .into_function()?;
+    let thread = lua.create_thread(protected)?;
+    args.push_front(LuaValue::Function(func));
+    let thread_id = sched.push_back(lua, thread.clone(), args)?;
+    Ok((thread, thread_id))
+}
+
+/**
+    Creates a result table from the values returned by a protected
+    thread, or from the error it was stopped with, if any.
+*/
+fn create_result_table<'lua>(
+    lua: &'lua Lua,
+    res: LuaResult<LuaMultiValue<'lua>>,
+) -> LuaResult<LuaTable<'lua>> {
+    let mut values = match res {
+        Err(e) => {
+            return TableBuilder::new(lua)?
+                .with_value("ok", false)?
+                .with_value("error", e.to_string())?
+                .build_readonly()
+        }
+        Ok(values) => values,
+    };
+    let ok = values
+        .pop_front()
+        .is_some_and(|v| v.as_boolean() == Some(true));
+    if ok {
+        TableBuilder::new(lua)?
+            .with_value("ok", true)?
+            .with_value("values", lua.create_sequence_from(values)?)?
+            .build_readonly()
+    } else {
+        TableBuilder::new(lua)?
+            .with_value("ok", false)?
+            .with_value("error", values.pop_front().unwrap_or(LuaValue::Nil))?
+            .build_readonly()
+    }
+}
+
+pub async fn task_all<'lua>(
+    lua: &'lua Lua,
+    funcs: Vec<LuaFunction<'lua>>,
+) -> LuaResult<LuaTable<'lua>> {
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+
+    let mut thread_ids = Vec::new();
+    for func in funcs {
+        let (_, thread_id) = spawn_protected(lua, sched, func, LuaMultiValue::new())?;
+        thread_ids.push(thread_id);
+    }
+
+    // NOTE: Waiting for other threads can only ever finish once those
+    // threads finish, so waiting here also parks the current thread
+    let results = sched
+        .park(join_all(
+            thread_ids
+                .into_iter()
+                .map(|thread_id| sched.wait_for_thread(lua, thread_id)),
+        ))
+        .await;
+
+    let results = results
+        .into_iter()
+        .map(|res| create_result_table(lua, res))
+        .collect::<LuaResult<Vec<_>>>()?;
+    lua.create_sequence_from(results)
+}
+
+pub async fn task_race<'lua>(
+    lua: &'lua Lua,
+    funcs: Vec<LuaFunction<'lua>>,
+) -> LuaResult<(usize, LuaTable<'lua>)> {
+    if funcs.is_empty() {
+        return Err(LuaError::runtime("Expected at least one function to race"));
+    }
+
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+
+    let mut threads = Vec::new();
+    let mut waits = Vec::new();
+    for func in funcs {
+        let (thread, thread_id) = spawn_protected(lua, sched, func, LuaMultiValue::new())?;
+        threads.push(thread);
+        waits.push(Box::pin(sched.wait_for_thread(lua, thread_id)));
+    }
+
+    let (res, index, remaining) = sched.park(select_all(waits)).await;
+    drop(remaining);
+
+    // The first thread to finish wins, and all others are cancelled
+    for (thread_index, thread) in threads.into_iter().enumerate() {
+        if thread_index != index {
+            sched.cancel_thread(lua, thread)?;
+        }
+    }
+
+    Ok((index + 1, create_result_table(lua, res)?))
+}
+
+pub async fn task_timeout<'lua>(
+    lua: &'lua Lua,
+    (secs, func, args): (f64, LuaFunction<'lua>, LuaMultiValue<'lua>),
+) -> LuaResult<LuaTable<'lua>> {
+    let duration = Duration::try_from_secs_f64(secs).map_err(|_| {
+        LuaError::RuntimeError(format!(
+            "Invalid timeout duration '{secs}' - must be a positive number"
+        ))
+    })?;
+
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+
+    let (thread, thread_id) = spawn_protected(lua, sched, func, args)?;
+
+    tokio::select! {
+        res = sched.wait_for_thread(lua, thread_id) => create_result_table(lua, res),
+        _ = time::sleep(duration) => {
+            sched.cancel_thread(lua, thread)?;
+            TableBuilder::new(lua)?
+                .with_value("ok", false)?
+                .with_value("timedOut", true)?
+                .with_value("error", format!("Timed out after {secs} seconds"))?
+                .build_readonly()
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use mlua::prelude::*;
+use tokio::sync::Notify;
+
+use crate::lune::{scheduler::Scheduler, util::TableBuilder};
+
+/**
+    Gets the deadline for the next tick of an interval, given the deadline of the previous tick.
+
+    Deadlines are always a whole number of periods after the interval started, so that
+    any time spent waiting to be resumed does not add up, and ticks that were missed
+    entirely, such as when a thread runs for longer than the period, are skipped.
+*/
+fn next_deadline(previous: Duration, period: Duration, now: Duration) -> Duration {
+    let next = previous + period;
+    if next > now {
+        return next;
+    }
+    let missed = ((now - next).as_secs_f64() / period.as_secs_f64()).floor() as u32;
+    next + period * (missed + 1)
+}
+
+pub fn task_interval<'lua>(
+    lua: &'lua Lua,
+    (secs, func, args): (f64, LuaFunction<'lua>, LuaMultiValue<'lua>),
+) -> LuaResult<LuaTable<'lua>>
+where
+    'lua: 'static,
+{
+    let period = match Duration::try_from_secs_f64(secs) {
+        Ok(period) if !period.is_zero() => period,
+        _ => {
+            return Err(LuaError::RuntimeError(format!(
+                "Invalid interval '{secs}' - must be a number greater than zero"
+            )))
+        }
+    };
+
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+
+    let func = lua.create_registry_value(func)?;
+    let args = lua.create_registry_value(args.into_vec())?;
+
+    let cancelled = Rc::new(Cell::new(false));
+    let cancel_notify = Rc::new(Notify::new());
+    let (interval_cancelled, interval_notify) = (Rc::clone(&cancelled), Rc::clone(&cancel_notify));
+
+    // NOTE: The first sleep is created right away, same as for task.delay,
+    // so that the interval starts from the moment that it was created
+    let mut deadline = sched.clock_elapsed() + period;
+    let mut sleep = sched.sleep(period);
+
+    sched.spawn_local(async move {
+        loop {
+            tokio::select! {
+                _ = sleep => {},
+                _ = interval_notify.notified() => {},
+            }
+            if interval_cancelled.get() || sched.has_exit_code() {
+                break;
+            }
+            let func = lua
+                .registry_value::<LuaFunction>(&func)
+                .expect("Failed to get interval function from registry");
+            let args = lua
+                .registry_value::<Vec<LuaValue>>(&args)
+                .expect("Failed to get interval args from registry");
+            let thread = lua
+                .create_thread(func)
+                .expect("Failed to create thread for interval");
+            sched
+                .push_back(lua, thread, LuaMultiValue::from_vec(args))
+                .expect("Failed to schedule interval thread");
+            let now = sched.clock_elapsed();
+            deadline = next_deadline(deadline, period, now);
+            sleep = sched.sleep(deadline - now);
+        }
+        lua.remove_registry_value(func).ok();
+        lua.remove_registry_value(args).ok();
+    });
+
+    let cancelled_active = Rc::clone(&cancelled);
+    TableBuilder::new(lua)?
+        .with_function("cancel", move |_, _: ()| {
+            cancelled.set(true);
+            cancel_notify.notify_one();
+            Ok(())
+        })?
+        .with_function("isActive", move |_, _: ()| Ok(!cancelled_active.get()))?
+        .build_readonly()
+}
@@ -1,13 +1,24 @@
-use std::time::Duration;
+use std::{future::Future, time::Duration};
 
 use mlua::prelude::*;
 
-use tokio::time::{self, Instant};
-
 use crate::lune::{scheduler::Scheduler, util::TableBuilder};
 
+mod channel;
+mod combinators;
+mod interval;
+mod sync;
 mod tof;
+mod worker;
+
+use channel::task_channel;
+use combinators::{task_all, task_race, task_timeout};
+use interval::task_interval;
+use sync::{task_event, task_mutex, task_semaphore};
 use tof::LuaThreadOrFunction;
+use worker::{create_parent_table, task_worker};
+
+pub use worker::{terminate_workers, WorkerPort};
 
 /*
     The spawn function needs special treatment,
@@ -16,13 +27,14 @@ use tof::LuaThreadOrFunction;
 
     1. Schedule this current thread at the front
     2. Schedule given thread/function at the front,
-       the previous schedule now comes right after
+       the previous schedule now comes right after,
+       and remember where it was spawned from
     3. Give control over to the scheduler, which will
        resume the above tasks in order when its ready
 */
 const SPAWN_IMPL_LUA: &str = r#"
 push(currentThread())
-local thread = push(...)
+local thread = spawn(...)
 yield()
 return thread
 "#;
@@ -45,10 +57,21 @@ pub fn create(lua: &'static Lua) -> LuaResult<LuaTable<'_>> {
             sched.push_front(lua, thread.clone(), args)?;
             Ok(thread)
         })?;
+    let spawn_front =
+        lua.create_function(|lua, (tof, args): (LuaThreadOrFunction, LuaMultiValue)| {
+            let thread = tof.into_thread(lua)?;
+            let sched = lua
+                .app_data_ref::<&Scheduler>()
+                .expect("Lua struct is missing scheduler");
+            sched.set_thread_origin(lua, &thread);
+            sched.push_front(lua, thread.clone(), args)?;
+            Ok(thread)
+        })?;
     let task_spawn_env = TableBuilder::new(lua)?
         .with_value("currentThread", coroutine_running)?
         .with_value("yield", coroutine_yield)?
         .with_value("push", push_front)?
+        .with_value("spawn", spawn_front)?
         .build_readonly()?;
     let task_spawn = lua
         .load(SPAWN_IMPL_LUA)
@@ -56,25 +79,38 @@ pub fn create(lua: &'static Lua) -> LuaResult<LuaTable<'_>> {
         .set_environment(task_spawn_env)
         .into_function()?;
 
+    let task_parent = match lua.app_data_ref::<WorkerPort>() {
+        Some(port) => LuaValue::Table(create_parent_table(lua, port.clone())?),
+        None => LuaValue::Nil,
+    };
+
     TableBuilder::new(lua)?
         .with_function("cancel", task_cancel)?
         .with_function("defer", task_defer)?
         .with_function("delay", task_delay)?
         .with_value("spawn", task_spawn)?
         .with_async_function("wait", task_wait)?
+        .with_function("channel", task_channel)?
+        .with_function("mutex", task_mutex)?
+        .with_function("semaphore", task_semaphore)?
+        .with_function("event", task_event)?
+        .with_async_function("all", task_all)?
+        .with_async_function("race", task_race)?
+        .with_async_function("timeout", task_timeout)?
+        .with_function("worker", task_worker)?
+        .with_function("interval", task_interval)?
+        .with_function("advance", task_advance)?
+        .with_function("inspect", task_inspect)?
+        .with_function("onError", task_on_error)?
+        .with_value("parent", task_parent)?
         .build_readonly()
 }
 
 fn task_cancel(lua: &Lua, thread: LuaThread) -> LuaResult<()> {
-    let close = lua
-        .globals()
-        .get::<_, LuaTable>("coroutine")?
-        .get::<_, LuaFunction>("close")?;
-    match close.call(thread) {
-        Err(LuaError::CoroutineInactive) => Ok(()),
-        Err(e) => Err(e),
-        Ok(()) => Ok(()),
-    }
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    sched.cancel_thread(lua, thread)
 }
 
 fn task_defer<'lua>(
@@ -85,6 +121,7 @@ fn task_defer<'lua>(
     let sched = lua
         .app_data_ref::<&Scheduler>()
         .expect("Lua struct is missing scheduler");
+    sched.set_thread_origin(lua, &thread);
     sched.push_back(lua, thread.clone(), args)?;
     Ok(thread)
 }
@@ -100,14 +137,17 @@ where
     'lua: 'static,
 {
     let thread = tof.into_thread(lua)?;
-    let sched = lua
+    let sched = *lua
         .app_data_ref::<&Scheduler>()
         .expect("Lua struct is missing scheduler");
+    sched.set_thread_origin(lua, &thread);
 
+    // NOTE: The sleep must be created right away, and not once the future is first
+    // polled, for the delay to be measured from the moment that it was called
+    let sleep = sched.sleep(Duration::from_secs_f64(secs));
     let thread2 = thread.clone();
     sched.spawn_thread(lua, thread.clone(), async move {
-        let duration = Duration::from_secs_f64(secs);
-        time::sleep(duration).await;
+        sleep.await;
         sched.push_back(lua, thread2, args)?;
         Ok(())
     })?;
@@ -115,12 +155,51 @@ where
     Ok(thread)
 }
 
-async fn task_wait(_: &Lua, secs: Option<f64>) -> LuaResult<f64> {
+fn task_wait(lua: &Lua, secs: Option<f64>) -> impl Future<Output = LuaResult<f64>> {
     let duration = Duration::from_secs_f64(secs.unwrap_or_default());
+    let sched = *lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+
+    // NOTE: Same as for delay, the sleep must be created right away
+    let before = sched.clock_elapsed();
+    let sleep = sched.sleep(duration);
+
+    async move {
+        sleep.await;
+        let after = sched.clock_elapsed();
+        Ok((after - before).as_secs_f64())
+    }
+}
+
+fn task_on_error(lua: &Lua, handler: Option<LuaFunction>) -> LuaResult<()> {
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    sched.set_error_handler(lua, handler)
+}
 
-    let before = Instant::now();
-    time::sleep(duration).await;
-    let after = Instant::now();
+fn task_inspect(lua: &Lua, _: ()) -> LuaResult<LuaTable<'_>> {
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    sched.inspect(lua)
+}
 
-    Ok((after - before).as_secs_f64())
+fn task_advance(lua: &Lua, secs: f64) -> LuaResult<()> {
+    let duration = Duration::try_from_secs_f64(secs).map_err(|_| {
+        LuaError::RuntimeError(format!(
+            "Invalid duration '{secs}' - must be a positive number"
+        ))
+    })?;
+    let sched = lua
+        .app_data_ref::<&Scheduler>()
+        .expect("Lua struct is missing scheduler");
+    if sched.advance_clock(duration) {
+        Ok(())
+    } else {
+        Err(LuaError::runtime(
+            "The clock can only be advanced when using a virtual clock",
+        ))
+    }
 }
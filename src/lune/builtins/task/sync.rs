@@ -0,0 +1,181 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use mlua::prelude::*;
+use tokio::sync::{Notify, Semaphore};
+
+use crate::lune::{scheduler::Scheduler, util::TableBuilder};
+
+pub fn task_mutex(lua: &'static Lua, _: ()) -> LuaResult<LuaTable<'static>> {
+    // NOTE: A mutex is a semaphore with a single permit, and permits are
+    // forgotten once acquired, since they are released from a separate call
+    let semaphore = Rc::new(Semaphore::new(1));
+    let semaphore_lock = Rc::clone(&semaphore);
+    let semaphore_try_lock = Rc::clone(&semaphore);
+    let semaphore_unlock = Rc::clone(&semaphore);
+    let semaphore_is_locked = semaphore;
+
+    TableBuilder::new(lua)?
+        .with_async_function("lock", move |lua, _: ()| {
+            let semaphore = Rc::clone(&semaphore_lock);
+            async move {
+                let sched = *lua
+                    .app_data_ref::<&Scheduler>()
+                    .expect("Lua struct is missing scheduler");
+                sched
+                    .park(semaphore.acquire())
+                    .await
+                    .into_lua_err()?
+                    .forget();
+                Ok(())
+            }
+        })?
+        .with_function("tryLock", move |_, _: ()| {
+            Ok(match semaphore_try_lock.try_acquire() {
+                Ok(permit) => {
+                    permit.forget();
+                    true
+                }
+                Err(_) => false,
+            })
+        })?
+        .with_function("unlock", move |_, _: ()| {
+            if semaphore_unlock.available_permits() > 0 {
+                return Err(LuaError::runtime(
+                    "Failed to unlock mutex - mutex is not locked",
+                ));
+            }
+            semaphore_unlock.add_permits(1);
+            Ok(())
+        })?
+        .with_function("isLocked", move |_, _: ()| {
+            Ok(semaphore_is_locked.available_permits() == 0)
+        })?
+        .build_readonly()
+}
+
+pub fn task_semaphore(lua: &'static Lua, permits: u32) -> LuaResult<LuaTable<'static>> {
+    if permits == 0 {
+        return Err(LuaError::runtime(
+            "Semaphore must be created with at least 1 permit",
+        ));
+    }
+
+    let semaphore = Rc::new(Semaphore::new(permits as usize));
+    let acquired = Rc::new(Cell::new(0_u32));
+    let (semaphore_acquire, acquired_acquire) = (Rc::clone(&semaphore), Rc::clone(&acquired));
+    let (semaphore_try_acquire, acquired_try_acquire) =
+        (Rc::clone(&semaphore), Rc::clone(&acquired));
+    let (semaphore_release, acquired_release) = (Rc::clone(&semaphore), acquired);
+    let semaphore_available = semaphore;
+
+    let check_count = move |count: Option<u32>| match count.unwrap_or(1) {
+        count if count == 0 || count > permits => Err(LuaError::RuntimeError(format!(
+            "Invalid permit count {count}, must be between 1 and {permits}"
+        ))),
+        count => Ok(count),
+    };
+
+    TableBuilder::new(lua)?
+        .with_async_function("acquire", move |lua, count: Option<u32>| {
+            let semaphore = Rc::clone(&semaphore_acquire);
+            let acquired = Rc::clone(&acquired_acquire);
+            async move {
+                let count = check_count(count)?;
+                let sched = *lua
+                    .app_data_ref::<&Scheduler>()
+                    .expect("Lua struct is missing scheduler");
+                sched
+                    .park(semaphore.acquire_many(count))
+                    .await
+                    .into_lua_err()?
+                    .forget();
+                acquired.set(acquired.get() + count);
+                Ok(())
+            }
+        })?
+        .with_function("tryAcquire", move |_, count: Option<u32>| {
+            let count = check_count(count)?;
+            Ok(match semaphore_try_acquire.try_acquire_many(count) {
+                Ok(permit) => {
+                    permit.forget();
+                    acquired_try_acquire.set(acquired_try_acquire.get() + count);
+                    true
+                }
+                Err(_) => false,
+            })
+        })?
+        .with_function("release", move |_, count: Option<u32>| {
+            let count = check_count(count)?;
+            if count > acquired_release.get() {
+                return Err(LuaError::RuntimeError(format!(
+                    "Failed to release {count} permit(s) - only {} are currently acquired",
+                    acquired_release.get()
+                )));
+            }
+            acquired_release.set(acquired_release.get() - count);
+            semaphore_release.add_permits(count as usize);
+            Ok(())
+        })?
+        .with_function("available", move |_, _: ()| {
+            Ok(semaphore_available.available_permits())
+        })?
+        .build_readonly()
+}
+
+/**
+    Shared state for a one-shot event, the value
+    it was set with is stored in the lua registry.
+*/
+#[derive(Debug, Clone, Default)]
+struct Event {
+    value: Rc<RefCell<Option<LuaRegistryKey>>>,
+    notify: Rc<Notify>,
+}
+
+impl Event {
+    fn is_set(&self) -> bool {
+        self.value.borrow().is_some()
+    }
+
+    fn set(&self, lua: &Lua, value: LuaValue) -> LuaResult<()> {
+        if self.is_set() {
+            return Err(LuaError::runtime(
+                "Failed to set event - event has already been set",
+            ));
+        }
+        self.value.replace(Some(lua.create_registry_value(value)?));
+        self.notify.notify_waiters();
+        Ok(())
+    }
+
+    async fn wait<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let sched = *lua
+            .app_data_ref::<&Scheduler>()
+            .expect("Lua struct is missing scheduler");
+        loop {
+            if let Some(key) = self.value.borrow().as_ref() {
+                return lua.registry_value(key);
+            }
+            sched.park(self.notify.notified()).await;
+        }
+    }
+}
+
+pub fn task_event(lua: &'static Lua, _: ()) -> LuaResult<LuaTable<'static>> {
+    let event = Event::default();
+    let event_set = event.clone();
+    let event_wait = event.clone();
+    let event_is_set = event;
+
+    TableBuilder::new(lua)?
+        .with_function("set", move |lua, value: LuaValue| event_set.set(lua, value))?
+        .with_async_function("wait", move |lua, _: ()| {
+            let event = event_wait.clone();
+            async move { event.wait(lua).await }
+        })?
+        .with_function("isSet", move |_, _: ()| Ok(event_is_set.is_set()))?
+        .build_readonly()
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::HashSet,
+    ffi::c_void,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use mlua::prelude::*;
+use serde_json::Value as JsonValue;
+use tokio::{
+    runtime,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch, Mutex as AsyncMutex,
+    },
+};
+
+use crate::lune::{scheduler::SchedulerExitHandle, util::TableBuilder, Lune};
+
+const LUA_SERIALIZE_OPTIONS: LuaSerializeOptions = LuaSerializeOptions::new()
+    .set_array_metatable(false)
+    .serialize_none_to_null(false)
+    .serialize_unit_to_null(false);
+
+const LUA_DESERIALIZE_OPTIONS: LuaDeserializeOptions = LuaDeserializeOptions::new()
+    .sort_keys(true)
+    .deny_recursive_tables(true)
+    .deny_unsupported_types(true);
+
+const TERMINATED_EXIT_CODE: u8 = 1;
+
+static NEXT_WORKER_ID: AtomicUsize = AtomicUsize::new(1);
+
+/**
+    Makes sure that all strings in the given value, including table keys, are valid UTF-8,
+    since messages are serialized as JSON, which can not contain any other strings.
+*/
+fn ensure_valid_utf8(value: &LuaValue, visited: &mut HashSet<*const c_void>) -> LuaResult<()> {
+    match value {
+        LuaValue::String(s) if s.to_str().is_err() => Err(LuaError::runtime(
+            "Failed to send message - strings in messages must be valid UTF-8",
+        )),
+        // NOTE: Recursive tables are rejected once the message is serialized,
+        // so here we only need to make sure that we do not visit them forever
+        LuaValue::Table(t) if visited.insert(t.to_pointer()) => {
+            for pair in t.clone().pairs::<LuaValue, LuaValue>() {
+                let (key, value) = pair?;
+                ensure_valid_utf8(&key, visited)?;
+                ensure_valid_utf8(&value, visited)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn encode_message(lua: &Lua, value: LuaValue) -> LuaResult<JsonValue> {
+    ensure_valid_utf8(&value, &mut HashSet::new())?;
+    lua.from_value_with(value, LUA_DESERIALIZE_OPTIONS)
+        .map_err(|e| LuaError::RuntimeError(format!("Failed to send message - {e}")))
+}
+
+fn decode_message(lua: &Lua, message: Option<JsonValue>) -> LuaResult<LuaValue<'_>> {
+    match message {
+        None => Ok(LuaValue::Nil),
+        Some(message) => lua.to_value_with(&message, LUA_SERIALIZE_OPTIONS),
+    }
+}
+
+/**
+    The end of a message channel that is given to the runtime of a worker.
+
+    Messages are sent between runtimes as serialized values, since
+    lua values can not be shared between different lua states.
+*/
+#[derive(Debug, Clone)]
+pub struct WorkerPort {
+    sender: UnboundedSender<JsonValue>,
+    receiver: Arc<AsyncMutex<UnboundedReceiver<JsonValue>>>,
+}
+
+/**
+    Shared state used by a parent runtime to stop one of its workers.
+*/
+#[derive(Debug, Default)]
+struct WorkerControl {
+    exit_handle: Mutex<Option<SchedulerExitHandle>>,
+    terminated: AtomicBool,
+}
+
+impl WorkerControl {
+    fn set_exit_handle(&self, handle: SchedulerExitHandle) {
+        // NOTE: The worker may have been terminated before its
+        // runtime was created, in which case it should exit instantly
+        if self.terminated.load(Ordering::SeqCst) {
+            handle.exit(TERMINATED_EXIT_CODE);
+        }
+        *self
+            .exit_handle
+            .lock()
+            .expect("Failed to lock worker exit handle") = Some(handle);
+    }
+
+    fn terminate(&self) {
+        self.terminated.store(true, Ordering::SeqCst);
+        if let Some(handle) = self
+            .exit_handle
+            .lock()
+            .expect("Failed to lock worker exit handle")
+            .as_ref()
+        {
+            handle.exit(TERMINATED_EXIT_CODE);
+        }
+    }
+}
+
+/**
+    Workers started by a runtime, stored in Lua app data, which
+    are terminated and waited for once the runtime has exited.
+*/
+#[derive(Debug, Default)]
+struct WorkerRegistry {
+    workers: Vec<(Arc<WorkerControl>, watch::Receiver<Option<bool>>)>,
+}
+
+impl WorkerRegistry {
+    fn add(&mut self, control: Arc<WorkerControl>, done: watch::Receiver<Option<bool>>) {
+        // NOTE: Workers that have already stopped do not need to be
+        // terminated, and keeping them around would leak their state
+        self.workers.retain(|(_, done)| done.borrow().is_none());
+        self.workers.push((control, done));
+    }
+}
+
+/**
+    Terminates all workers started by the given runtime, and waits for them to stop.
+
+    This must be called once the runtime has exited, since workers would
+    otherwise keep running in the background after their parent has stopped.
+*/
+pub async fn terminate_workers(lua: &Lua) {
+    let workers = match lua.app_data_mut::<WorkerRegistry>() {
+        Some(mut registry) => std::mem::take(&mut registry.workers),
+        None => return,
+    };
+    for (control, _) in &workers {
+        control.terminate();
+    }
+    for (_, mut done) in workers {
+        // NOTE: This only fails if the worker thread panicked, and is then already gone
+        done.wait_for(Option::is_some).await.ok();
+    }
+}
+
+/**
+    Runs a worker script to completion in a new runtime, with its own
+    scheduler, returning `true` if the worker exited successfully.
+
+    This blocks the current thread until the worker has finished running.
+*/
+fn run_worker(
+    script_name: String,
+    script_contents: Vec<u8>,
+    port: WorkerPort,
+    control: Arc<WorkerControl>,
+) -> bool {
+    let rt = match runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+    {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to create runtime for worker - {e}");
+            return false;
+        }
+    };
+    let (ok, lune) = rt.block_on(async move {
+        let mut lune = Lune::new().with_worker_port(port);
+        control.set_exit_handle(lune.exit_handle());
+        let ok = match lune.run(script_name, script_contents).await {
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+            Ok(code) => code == ExitCode::SUCCESS,
+        };
+        (ok, lune)
+    });
+    // NOTE: Dropping the tokio runtime also drops any tasks that are still
+    // spawned on it, which must happen before the worker runtime is freed
+    drop(rt);
+    // SAFETY: There are no tasks left that could reference the worker runtime,
+    // and it has never been cloned, so nothing else is able to use it
+    unsafe { lune.destroy() };
+    ok
+}
+
+/**
+    Resolves the path to a worker script, relative to the script that is
+    calling `task.worker`, the same way that paths given to `require` are.
+*/
+fn resolve_worker_path(lua: &Lua, path: &str) -> LuaResult<PathBuf> {
+    // NOTE: We skip any C functions here, since the worker
+    // may have been started using something like pcall
+    let source = (1..)
+        .map_while(|level| lua.inspect_stack(level))
+        .find(|info| info.source().what != "C")
+        .and_then(|info| info.source().source.map(|s| s.to_string()))
+        .ok_or_else(|| LuaError::runtime("Failed to get source of worker script caller"))?;
+    let path = Path::new(&source)
+        .parent()
+        .ok_or_else(|| LuaError::runtime("Failed to get parent path of source"))?
+        .join(path);
+    Ok(path_clean::clean(path))
+}
+
+pub fn task_worker(lua: &'static Lua, path: String) -> LuaResult<LuaTable<'static>> {
+    let path = resolve_worker_path(lua, &path)?;
+    let script_contents = fs::read(&path).map_err(|e| {
+        LuaError::RuntimeError(format!(
+            "Failed to read worker script '{}' - {e}",
+            path.display()
+        ))
+    })?;
+    // NOTE: We skip the extension here to remove it from stack traces, same as the cli
+    let script_name = path.with_extension("").display().to_string();
+
+    let (parent_sender, worker_receiver) = unbounded_channel();
+    let (worker_sender, parent_receiver) = unbounded_channel();
+    let (done_sender, done_receiver) = watch::channel(None);
+    let control = Arc::new(WorkerControl::default());
+
+    let port = WorkerPort {
+        sender: worker_sender,
+        receiver: Arc::new(AsyncMutex::new(worker_receiver)),
+    };
+    let worker_control = Arc::clone(&control);
+    thread::Builder::new()
+        .name(format!(
+            "lune-worker-{}",
+            NEXT_WORKER_ID.fetch_add(1, Ordering::Relaxed)
+        ))
+        .spawn(move || {
+            let ok = run_worker(script_name, script_contents, port, worker_control);
+            done_sender.send_replace(Some(ok));
+        })
+        .map_err(|e| LuaError::RuntimeError(format!("Failed to start worker - {e}")))?;
+
+    if lua.app_data_ref::<WorkerRegistry>().is_none() {
+        lua.set_app_data(WorkerRegistry::default());
+    }
+    lua.app_data_mut::<WorkerRegistry>()
+        .expect("Missing worker registry in Lua app data")
+        .add(Arc::clone(&control), done_receiver.clone());
+
+    let receiver = Rc::new(AsyncMutex::new(parent_receiver));
+    let done_wait = done_receiver.clone();
+    let done_is_running = done_receiver;
+
+    TableBuilder::new(lua)?
+        .with_function("send", move |lua, value: LuaValue| {
+            let message = encode_message(lua, value)?;
+            parent_sender
+                .send(message)
+                .map_err(|_| LuaError::runtime("Failed to send message - worker has stopped"))
+        })?
+        .with_async_function("recv", move |lua, _: ()| {
+            let receiver = Rc::clone(&receiver);
+            async move {
+                // NOTE: The sender is dropped together with the worker runtime once
+                // it has stopped, after which this will return any remaining messages
+                let message = receiver.lock().await.recv().await;
+                decode_message(lua, message)
+            }
+        })?
+        .with_function("terminate", move |_, _: ()| {
+            control.terminate();
+            Ok(())
+        })?
+        .with_async_function("wait", move |_, _: ()| {
+            let mut done = done_wait.clone();
+            async move {
+                let ok = *done.wait_for(Option::is_some).await.into_lua_err()?;
+                Ok(ok.unwrap_or_default())
+            }
+        })?
+        .with_function("isRunning", move |_, _: ()| {
+            Ok(done_is_running.borrow().is_none())
+        })?
+        .build_readonly()
+}
+
+pub fn create_parent_table(lua: &'static Lua, port: WorkerPort) -> LuaResult<LuaTable<'static>> {
+    let sender = port.sender;
+    let receiver = port.receiver;
+
+    TableBuilder::new(lua)?
+        .with_function("send", move |lua, value: LuaValue| {
+            let message = encode_message(lua, value)?;
+            sender
+                .send(message)
+                .map_err(|_| LuaError::runtime("Failed to send message - parent has stopped"))
+        })?
+        .with_async_function("recv", move |lua, _: ()| {
+            let receiver = Arc::clone(&receiver);
+            async move {
+                let message = receiver.lock().await.recv().await;
+                decode_message(lua, message)
+            }
+        })?
+        .build_readonly()
+}
@@ -1,21 +1,22 @@
 use mlua::prelude::*;
-use tokio::io::{self, AsyncWriteExt};
 
 use crate::lune::{
     scheduler::LuaSchedulerExt,
-    util::formatting::{format_label, pretty_format_multi_value},
+    util::{
+        formatting::{format_label, pretty_format_multi_value, FormatOptions},
+        progress::write_stderr,
+    },
 };
 
 pub fn create(lua: &'static Lua) -> LuaResult<impl IntoLua<'_>> {
-    lua.create_async_function(|_, args: LuaMultiValue| async move {
+    lua.create_async_function(|lua, args: LuaMultiValue| async move {
+        let options = FormatOptions::from_app_data(lua);
         let formatted = format!(
             "{}\n{}\n",
             format_label("warn"),
-            pretty_format_multi_value(&args)?
+            pretty_format_multi_value(&args, &options)?
         );
-        let mut stdout = io::stderr();
-        stdout.write_all(formatted.as_bytes()).await?;
-        stdout.flush().await?;
+        write_stderr(formatted.as_bytes()).await?;
         Ok(())
     })
 }
@@ -1,4 +1,4 @@
-use std::process::ExitCode;
+use std::{path::PathBuf, process::ExitCode, time::Duration};
 
 use mlua::Lua;
 
@@ -9,9 +9,16 @@ mod scheduler;
 
 pub(crate) mod util;
 
-use self::scheduler::{LuaSchedulerExt, Scheduler};
+use self::{
+    builtins::{
+        restore_terminal_mode, terminate_workers, NetCassetteConfig, NetCassetteMode, WorkerPort,
+    },
+    scheduler::{LuaSchedulerExt, Scheduler, SchedulerExitHandle},
+    util::formatting::FormatOptions,
+};
 
 pub use error::LuneError;
+pub use scheduler::{ErrorPolicy, TimeLimitAction};
 
 // TODO: Rename this struct to "Runtime" instead for the
 // next breaking release, it's a more fitting name and
@@ -40,6 +47,7 @@ impl Lune {
 
         lua.set_scheduler(scheduler);
         lua.set_app_data(Vec::<String>::new());
+        lua.set_app_data(FormatOptions::default());
         globals::inject_all(lua).expect("Failed to inject lua globals");
 
         Self {
@@ -61,6 +69,114 @@ impl Lune {
         self
     }
 
+    /**
+        Records all requests sent using `net.request`, together with their
+        responses, into a cassette file at the given path.
+
+        Takes precedence over the `LUNE_NET_RECORD` and `LUNE_NET_REPLAY` environment variables.
+    */
+    pub fn with_net_recording(self, path: impl Into<PathBuf>) -> Self {
+        self.lua.set_app_data(NetCassetteConfig {
+            mode: NetCassetteMode::Record,
+            path: path.into(),
+        });
+        self
+    }
+
+    /**
+        Serves responses for all requests sent using `net.request` from a
+        cassette file at the given path, instead of sending any requests.
+
+        Requests that were not recorded in the cassette file will throw an error.
+
+        Takes precedence over the `LUNE_NET_RECORD` and `LUNE_NET_REPLAY` environment variables.
+    */
+    pub fn with_net_replay(self, path: impl Into<PathBuf>) -> Self {
+        self.lua.set_app_data(NetCassetteConfig {
+            mode: NetCassetteMode::Replay,
+            path: path.into(),
+        });
+        self
+    }
+
+    /**
+        Runs all scripts using a virtual clock instead of real time, mostly useful for tests.
+
+        When using a virtual clock, `task.wait`, `task.delay` and `datetime.now` follow a
+        clock that jumps straight ahead to the next deadline whenever all threads are waiting
+        for it, or for each other, making scripts run instantly and deterministically. The clock
+        does not move forward on its own while anything else is pending, such as a request, a
+        web server, or a message from a worker, but may be moved forward manually using `task.advance`.
+
+        Runtimes started by scripts, such as workers, always use real time. The `LUNE_VIRTUAL_CLOCK`
+        environment variable is only read by the CLI, and has no effect on embedded runtimes.
+    */
+    pub fn with_virtual_clock(self) -> Self {
+        self.scheduler.enable_virtual_clock();
+        self
+    }
+
+    /**
+        Sets what to do when a lua thread throws an error that is not handled, either by
+        using `pcall` or by an error handler set using `task.onError` - the default is
+        to emit the error and keep running, exiting with a non-zero exit code once finished.
+    */
+    pub fn with_error_policy(self, policy: ErrorPolicy) -> Self {
+        self.scheduler.set_error_policy(policy);
+        self
+    }
+
+    /**
+        Limits how long a lua thread may run each time it is resumed, without yielding.
+
+        Any lua thread that runs for longer than the given `limit`, such as a thread stuck in an
+        infinite loop, either throws an error that may be caught using `pcall`, or is forced to
+        yield, letting other threads and futures run before it is resumed again - depending on
+        the given `action`. Without a time limit, such a thread would freeze the entire runtime.
+
+        Note that threads may only be forced to yield where they could have yielded on
+        their own, and will throw an error instead when running inside of a metamethod.
+    */
+    pub fn with_time_limit(self, limit: Duration, action: TimeLimitAction) -> Self {
+        self.scheduler.set_time_limit(limit, action);
+        self
+    }
+
+    /**
+        Connects this runtime to the parent runtime that started it as a
+        worker, letting scripts send and receive messages using `task.parent`.
+    */
+    pub(crate) fn with_worker_port(self, port: WorkerPort) -> Self {
+        self.lua.set_app_data(port);
+        self
+    }
+
+    /**
+        Creates a new handle that can be used to stop this runtime from any thread.
+    */
+    pub(crate) fn exit_handle(&self) -> SchedulerExitHandle {
+        self.scheduler.exit_handle()
+    }
+
+    /**
+        Frees the Luau VM and task scheduler used by this runtime,
+        which would otherwise live for the rest of the program.
+
+        # Safety
+
+        The runtime must not be used after this, and no clones of it may exist. Any futures that
+        may still reference it, such as tasks spawned on the tokio runtime that it was running on,
+        must already have been dropped, meaning that the tokio runtime must have been shut down.
+    */
+    pub(crate) unsafe fn destroy(self) {
+        // NOTE: Futures and threads stored in the scheduler hold on to
+        // lua values, so the scheduler must be dropped before the lua VM
+        drop(Box::from_raw(
+            (self.scheduler as *const Scheduler<'static>).cast_mut(),
+        ));
+        drop(Lua::from_static(self.lua));
+    }
+
     /**
         Runs a Lune script inside of the current runtime.
 
@@ -76,8 +192,21 @@ impl Lune {
             .load(script_contents.as_ref())
             .set_name(script_name.as_ref());
 
-        self.scheduler.push_back(self.lua, main, ())?;
+        let main_id = self.scheduler.push_back(self.lua, main, ())?;
+        self.scheduler.set_main_thread(main_id);
+
+        let exit_code = self.scheduler.run_to_completion(self.lua).await;
+
+        // NOTE: Workers must not outlive the runtime that started them, and
+        // this runs after exit hooks, which may still communicate with workers
+        terminate_workers(self.lua).await;
+
+        // NOTE: Workers share the terminal with their parent runtime,
+        // which may still be using raw mode once the worker has finished
+        if self.lua.app_data_ref::<WorkerPort>().is_none() {
+            restore_terminal_mode();
+        }
 
-        Ok(self.scheduler.run_to_completion(self.lua).await)
+        Ok(exit_code)
     }
 }
@@ -1,16 +1,24 @@
-use futures_util::Future;
+use std::{mem, panic::Location};
+
+use futures_util::{future::abortable, stream::FuturesUnordered, Future};
 use mlua::prelude::*;
 use tokio::{
     sync::oneshot::{self, Receiver},
     task,
 };
 
-use super::{IntoLuaThread, Scheduler};
+use super::{
+    inspect::{SchedulerFutureGuard, SchedulerFutureInfo, SchedulerFutureKind},
+    IntoLuaThread, Scheduler, SchedulerFuture, SchedulerThreadId,
+};
 
 impl<'fut> Scheduler<'fut> {
     /**
         Checks if there are any futures to run, for
         lua futures and background futures respectively.
+
+        Note that detached background futures are not counted here,
+        use [`has_detached_futures`] to check for those separately.
     */
     pub(super) fn has_futures(&self) -> (bool, bool) {
         (
@@ -23,10 +31,78 @@ impl<'fut> Scheduler<'fut> {
                 .try_lock()
                 .expect("Failed to lock background futures for check")
                 .len()
-                > 0,
+                + self.num_pending_background_futures()
+                > self.state.num_detached_futures(),
         )
     }
 
+    fn num_pending_background_futures(&self) -> usize {
+        self.futures_background_pending
+            .lock()
+            .expect("Failed to lock pending background futures")
+            .len()
+    }
+
+    /**
+        Pushes a future to the background futures queue.
+
+        If background futures are currently being resumed, the future is
+        instead stored as pending, and will be moved to the queue as soon
+        as the current resumption of background futures has finished.
+    */
+    fn push_background(
+        &self,
+        kind: SchedulerFutureKind,
+        location: &Location,
+        fut: SchedulerFuture<'static>,
+    ) {
+        let guard = SchedulerFutureGuard::new(
+            &self.futures_info,
+            SchedulerFutureInfo::new(None, kind, location),
+        );
+        let fut = Box::pin(async move {
+            fut.await;
+            drop(guard);
+        });
+        match self.futures_background.try_lock() {
+            Ok(futs) => futs.push(fut),
+            Err(_) => self
+                .futures_background_pending
+                .lock()
+                .expect("Failed to lock pending background futures")
+                .push(fut),
+        }
+
+        // NOTE: We might be resuming lua futures, need to signal that a
+        // new background future is ready to break out of futures resumption
+        self.state.message_sender().send_spawned_background_future();
+    }
+
+    /**
+        Moves all pending background futures to the background futures queue.
+    */
+    pub(super) fn queue_pending_background_futures(
+        &self,
+        futs: &FuturesUnordered<SchedulerFuture<'static>>,
+    ) {
+        let pending = mem::take(
+            &mut *self
+                .futures_background_pending
+                .lock()
+                .expect("Failed to lock pending background futures"),
+        );
+        for fut in pending {
+            futs.push(fut);
+        }
+    }
+
+    /**
+        Checks if there are any detached background futures to run.
+    */
+    pub(super) fn has_detached_futures(&self) -> bool {
+        self.state.num_detached_futures() > 0
+    }
+
     /**
         Schedules a plain future to run in the background.
 
@@ -39,6 +115,7 @@ impl<'fut> Scheduler<'fut> {
         This [`Receiver`] may be safely ignored if the result of the
         spawned future is not needed, the future will run either way.
     */
+    #[track_caller]
     pub fn spawn<F>(&self, fut: F) -> Receiver<F::Output>
     where
         F: Future + Send + 'static,
@@ -53,17 +130,13 @@ impl<'fut> Scheduler<'fut> {
 
         // NOTE: We must spawn a future on our scheduler which awaits
         // the handle from tokio to start driving our future properly
-        let futs = self
-            .futures_background
-            .try_lock()
-            .expect("Failed to lock futures queue for background tasks");
-        futs.push(Box::pin(async move {
-            handle.await.ok();
-        }));
-
-        // NOTE: We might be resuming lua futures, need to signal that a
-        // new background future is ready to break out of futures resumption
-        self.state.message_sender().send_spawned_background_future();
+        self.push_background(
+            SchedulerFutureKind::Background,
+            Location::caller(),
+            Box::pin(async move {
+                handle.await.ok();
+            }),
+        );
 
         rx
     }
@@ -72,6 +145,7 @@ impl<'fut> Scheduler<'fut> {
         Equivalent to [`spawn`], except the future is only
         spawned on the Lune scheduler, and on the main thread.
     */
+    #[track_caller]
     pub fn spawn_local<F>(&self, fut: F) -> Receiver<F::Output>
     where
         F: Future + 'static,
@@ -79,27 +153,50 @@ impl<'fut> Scheduler<'fut> {
     {
         let (tx, rx) = oneshot::channel();
 
-        let futs = self
-            .futures_background
-            .try_lock()
-            .expect("Failed to lock futures queue for background tasks");
-        futs.push(Box::pin(async move {
-            let res = fut.await;
-            tx.send(res).ok();
-        }));
-
-        // NOTE: We might be resuming lua futures, need to signal that a
-        // new background future is ready to break out of futures resumption
-        self.state.message_sender().send_spawned_background_future();
+        self.push_background(
+            SchedulerFutureKind::Background,
+            Location::caller(),
+            Box::pin(async move {
+                let res = fut.await;
+                tx.send(res).ok();
+            }),
+        );
 
         rx
     }
 
+    /**
+        Equivalent to [`spawn_local`], except the future does not keep the scheduler alive.
+
+        Once only detached futures are left, the scheduler will run to completion
+        and drop them, meaning that they may never complete. This is useful for
+        futures that only listen for events, such as signals sent to the process.
+    */
+    #[track_caller]
+    pub fn spawn_local_detached<F>(&self, fut: F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let state = self.state.clone();
+        state.increment_detached_futures();
+        self.push_background(
+            SchedulerFutureKind::Detached,
+            Location::caller(),
+            Box::pin(async move {
+                fut.await;
+                state.decrement_detached_futures();
+            }),
+        );
+    }
+
     /**
         Schedules the given `thread` to run when the given `fut` completes.
 
         If the given future returns a [`LuaError`], that error will be passed to the given `thread`.
+
+        If the given `thread` is cancelled before the future completes, the future is dropped.
     */
+    #[track_caller]
     pub fn spawn_thread<F, FR>(
         &'fut self,
         lua: &'fut Lua,
@@ -116,8 +213,32 @@ impl<'fut> Scheduler<'fut> {
             can't schedule future lua threads during futures resumption",
         );
 
+        let mut info =
+            SchedulerFutureInfo::new(Some(lua), SchedulerFutureKind::Lua, Location::caller());
+        info.thread = Some((
+            SchedulerThreadId::from(&thread),
+            lua.create_registry_value(thread.clone())?,
+        ));
+        let guard = SchedulerFutureGuard::new(&self.futures_info, info);
+
+        let (fut, handle) = abortable(fut);
+        let handle_id = self
+            .thread_futures
+            .lock()
+            .expect("Failed to lock thread futures")
+            .insert(SchedulerThreadId::from(&thread), handle);
+
         futs.push(Box::pin(async move {
-            match fut.await.and_then(|rets| rets.into_lua_multi(lua)) {
+            let res = fut.await;
+            drop(guard);
+            let Ok(res) = res else {
+                return; // Thread was cancelled, and should not be resumed
+            };
+            self.thread_futures
+                .lock()
+                .expect("Failed to lock thread futures")
+                .remove(handle_id);
+            match res.and_then(|rets| rets.into_lua_multi(lua)) {
                 Err(e) => {
                     self.push_err(lua, thread, e)
                         .expect("Failed to schedule future err thread");
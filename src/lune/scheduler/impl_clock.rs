@@ -0,0 +1,300 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, SystemTime},
+};
+
+use futures_util::future;
+use tokio::time::{self, Instant, Sleep};
+
+use super::{inspect::SchedulerFutureKind, Scheduler};
+
+/**
+    A virtual clock, where time only moves forward when it is advanced manually.
+*/
+#[derive(Debug)]
+struct VirtualClock {
+    system_time: SystemTime,
+    elapsed: Duration,
+    timers: BTreeMap<(Duration, usize), Option<Waker>>,
+    next_timer_id: usize,
+}
+
+impl VirtualClock {
+    fn advance_to(&mut self, elapsed: Duration) {
+        self.elapsed = self.elapsed.max(elapsed);
+        // NOTE: Timers are woken in order of their deadlines, and timers that share
+        // the same deadline are woken in the order they were created, which
+        // makes the order that threads are resumed in completely deterministic
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > self.elapsed {
+                break;
+            }
+            if let Some(waker) = entry.remove() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/**
+    The clock used by the scheduler for anything that waits for an amount of time.
+
+    By default this follows real time, but it may be switched to a virtual clock,
+    mostly useful for tests, where waiting happens instantly and deterministically.
+*/
+#[derive(Debug)]
+pub(super) struct SchedulerClock {
+    start: Instant,
+    virtual_clock: Arc<Mutex<Option<VirtualClock>>>,
+}
+
+impl Default for SchedulerClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            virtual_clock: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/**
+    A future that completes once the scheduler clock has reached the given deadline.
+*/
+pub struct SchedulerSleep {
+    inner: SchedulerSleepInner,
+}
+
+enum SchedulerSleepInner {
+    Real(Pin<Box<Sleep>>),
+    Virtual {
+        clock: Arc<Mutex<Option<VirtualClock>>>,
+        key: (Duration, usize),
+    },
+}
+
+impl Future for SchedulerSleep {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.inner {
+            SchedulerSleepInner::Real(sleep) => sleep.as_mut().poll(cx),
+            SchedulerSleepInner::Virtual { clock, key } => {
+                let mut clock = clock.lock().expect("Failed to lock virtual clock");
+                let clock = clock.as_mut().expect("Virtual clock was disabled");
+                if clock.elapsed >= key.0 {
+                    Poll::Ready(())
+                } else {
+                    clock.timers.insert(*key, Some(cx.waker().clone()));
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SchedulerSleep {
+    fn drop(&mut self) {
+        // NOTE: Sleeps that are dropped before completing, such as when a thread
+        // is cancelled, should not be waited for when advancing the clock
+        if let SchedulerSleepInner::Virtual { clock, key } = &self.inner {
+            if let Ok(mut clock) = clock.lock() {
+                if let Some(clock) = clock.as_mut() {
+                    clock.timers.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl<'fut> Scheduler<'fut> {
+    /**
+        Switches the scheduler to a virtual clock, starting at the current time.
+
+        When using a virtual clock, time only moves forward once all lua threads
+        and futures are waiting for the clock or for each other, and it then moves directly
+        to the next deadline, or when it is advanced manually using [`Scheduler::advance_clock`].
+    */
+    pub fn enable_virtual_clock(&self) {
+        let mut clock = self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock");
+        if clock.is_none() {
+            *clock = Some(VirtualClock {
+                system_time: SystemTime::now(),
+                elapsed: Duration::ZERO,
+                timers: BTreeMap::new(),
+                next_timer_id: 0,
+            });
+        }
+    }
+
+    /**
+        Gets the amount of time that has passed on the scheduler clock since the scheduler was created.
+    */
+    pub fn clock_elapsed(&self) -> Duration {
+        match self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_ref()
+        {
+            Some(clock) => clock.elapsed,
+            None => self.clock.start.elapsed(),
+        }
+    }
+
+    /**
+        Gets the current system time, according to the scheduler clock.
+    */
+    pub fn clock_system_time(&self) -> SystemTime {
+        match self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_ref()
+        {
+            Some(clock) => clock.system_time + clock.elapsed,
+            None => SystemTime::now(),
+        }
+    }
+
+    /**
+        Creates a future that completes after the given duration has passed on the scheduler clock.
+    */
+    pub fn sleep(&self, duration: Duration) -> SchedulerSleep {
+        let inner = match self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_mut()
+        {
+            None => SchedulerSleepInner::Real(Box::pin(time::sleep(duration))),
+            Some(clock) => {
+                let key = (clock.elapsed + duration, clock.next_timer_id);
+                clock.next_timer_id += 1;
+                clock.timers.insert(key, None);
+                SchedulerSleepInner::Virtual {
+                    clock: Arc::clone(&self.clock.virtual_clock),
+                    key,
+                }
+            }
+        };
+        SchedulerSleep { inner }
+    }
+
+    /**
+        Advances the virtual clock by the given duration, waking any sleeps that are due.
+
+        Returns `false` if the scheduler is not using a virtual clock.
+    */
+    pub fn advance_clock(&self, duration: Duration) -> bool {
+        match self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_mut()
+        {
+            None => false,
+            Some(clock) => {
+                clock.advance_to(clock.elapsed + duration);
+                true
+            }
+        }
+    }
+
+    /**
+        Checks if there are any sleeps waiting for the virtual clock to advance.
+    */
+    pub(super) fn has_virtual_timers(&self) -> bool {
+        self.clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_ref()
+            .is_some_and(|clock| !clock.timers.is_empty())
+    }
+
+    /**
+        Checks if every lua and background future is waiting for either the virtual
+        clock, or for another lua thread, with no lua threads left to resume, which
+        means that nothing else can make progress until the virtual clock advances.
+
+        This uses the same accounting as deadlock detection, where sleeps that are
+        waiting for the virtual clock count the same way that parked futures do.
+    */
+    fn is_waiting_for_clock(&self) -> bool {
+        if self.has_thread() {
+            return false;
+        }
+        let (num_lua, num_background) = {
+            let infos = self
+                .futures_info
+                .lock()
+                .expect("Failed to lock futures info");
+            (
+                infos.count(SchedulerFutureKind::Lua),
+                infos.count(SchedulerFutureKind::Background),
+            )
+        };
+        // NOTE: Sleeps only have a waker once they have been polled and are waiting,
+        // and it is removed as soon as they are woken, same as unparking a future
+        let num_sleeping = self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_ref()
+            .map_or(0, |clock| {
+                clock
+                    .timers
+                    .values()
+                    .filter(|waker| waker.is_some())
+                    .count()
+            });
+        num_lua + num_background == self.state.num_parked_futures() + num_sleeping
+    }
+
+    /**
+        Advances the virtual clock to the next deadline, once all
+        other futures have had a chance to make progress first.
+
+        This future never completes if there are no sleeps to wake, or if there are lua
+        threads waiting to be resumed, or any other futures that are not waiting for the
+        clock, such as requests or web servers, since those must always be able to run
+        before the clock moves forward for the order of threads to stay deterministic.
+    */
+    pub(super) async fn advance_clock_when_idle(&self) {
+        tokio::task::yield_now().await;
+        if !self.is_waiting_for_clock() {
+            future::pending::<()>().await;
+        }
+        let advanced = match self
+            .clock
+            .virtual_clock
+            .lock()
+            .expect("Failed to lock virtual clock")
+            .as_mut()
+        {
+            None => false,
+            Some(clock) => match clock.timers.keys().next().copied() {
+                None => false,
+                Some((deadline, _)) => {
+                    clock.advance_to(deadline);
+                    true
+                }
+            },
+        };
+        if !advanced {
+            future::pending::<()>().await;
+        }
+    }
+}
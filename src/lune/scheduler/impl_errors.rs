@@ -0,0 +1,214 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use mlua::prelude::*;
+
+use crate::lune::util::{
+    formatting::pretty_format_luau_error, traits::LuaEmitErrorExt, TableBuilder,
+};
+
+use super::{inspect::lua_call_site, Scheduler, SchedulerThreadId};
+
+/**
+    What the scheduler should do when a lua thread throws an error
+    that is not handled, either using `pcall` or by an error handler.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /**
+        Emits the error and keeps running, exiting with
+        a non-zero exit code once all threads have finished.
+    */
+    #[default]
+    Continue,
+    /**
+        Emits the error and exits right away, with a non-zero exit code.
+    */
+    Abort,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "continue" => Ok(Self::Continue),
+            "abort" => Ok(Self::Abort),
+            _ => Err(format!(
+                "Invalid error policy '{s}' - expected 'continue' or 'abort'"
+            )),
+        }
+    }
+}
+
+/**
+    State used by the scheduler to handle errors thrown by lua threads.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SchedulerErrors {
+    policy: ErrorPolicy,
+    handler: Option<LuaRegistryKey>,
+    handler_threads: HashSet<SchedulerThreadId>,
+    main_threads: HashSet<SchedulerThreadId>,
+    thread_origins: HashMap<SchedulerThreadId, String>,
+}
+
+/**
+    Splits a formatted error into its message and its stack trace, if any.
+*/
+fn split_error(err: &LuaError) -> (String, String) {
+    let formatted = pretty_format_luau_error(err, false);
+    let mut message = Vec::new();
+    let mut traceback = Vec::new();
+    let mut in_stack = false;
+    for line in formatted.lines() {
+        match line {
+            "[Stack Begin]" => in_stack = true,
+            "[Stack End]" => in_stack = false,
+            line if in_stack => traceback.push(line),
+            line => message.push(line),
+        }
+    }
+    (message.join("\n"), traceback.join("\n"))
+}
+
+impl<'fut> Scheduler<'fut> {
+    /**
+        Sets what the scheduler should do when a lua thread throws an error that is not handled.
+    */
+    pub fn set_error_policy(&self, policy: ErrorPolicy) {
+        self.errors
+            .lock()
+            .expect("Failed to lock scheduler errors")
+            .policy = policy;
+    }
+
+    /**
+        Sets the function that any errors thrown by lua threads will be given to, instead of being
+        emitted, or removes the current function if `None` is given.
+
+        The handler runs in a new lua thread for each error, and if the handler itself
+        throws an error, that error is handled using the current [`ErrorPolicy`].
+    */
+    pub fn set_error_handler<'lua>(
+        &self,
+        lua: &'lua Lua,
+        handler: Option<LuaFunction<'lua>>,
+    ) -> LuaResult<()> {
+        let key = handler.map(|h| lua.create_registry_value(h)).transpose()?;
+        let previous = std::mem::replace(
+            &mut self
+                .errors
+                .lock()
+                .expect("Failed to lock scheduler errors")
+                .handler,
+            key,
+        );
+        if let Some(previous) = previous {
+            lua.remove_registry_value(previous)?;
+        }
+        Ok(())
+    }
+
+    /**
+        Records the lua script and line that is currently running as the place
+        where the given thread was spawned, which will be shown for any errors.
+    */
+    pub fn set_thread_origin(&self, lua: &Lua, thread: &LuaThread) {
+        if let Some(origin) = lua_call_site(lua) {
+            self.errors
+                .lock()
+                .expect("Failed to lock scheduler errors")
+                .thread_origins
+                .insert(SchedulerThreadId::from(thread), origin);
+        }
+    }
+
+    /**
+        Marks the given thread as the main thread of a script, meaning that any errors it
+        throws are never given to the error handler, and always count as unhandled errors.
+    */
+    pub fn set_main_thread(&self, thread_id: SchedulerThreadId) {
+        self.errors
+            .lock()
+            .expect("Failed to lock scheduler errors")
+            .main_threads
+            .insert(thread_id);
+    }
+
+    /**
+        Removes any error handling state for a thread that has finished running.
+    */
+    pub(super) fn forget_thread_errors(&self, thread_id: SchedulerThreadId) {
+        let mut errors = self.errors.lock().expect("Failed to lock scheduler errors");
+        errors.thread_origins.remove(&thread_id);
+        errors.handler_threads.remove(&thread_id);
+        errors.main_threads.remove(&thread_id);
+    }
+
+    /**
+        Handles an error thrown by a lua thread, either by giving it to the error handler
+        function, or by emitting it and then following the current [`ErrorPolicy`].
+    */
+    pub(super) fn handle_thread_error<'lua>(
+        &self,
+        lua: &'lua Lua,
+        thread: LuaThread<'lua>,
+        thread_id: SchedulerThreadId,
+        err: LuaError,
+    ) {
+        let (policy, origin, handler) = {
+            let mut errors = self.errors.lock().expect("Failed to lock scheduler errors");
+            let origin = errors.thread_origins.remove(&thread_id);
+            // NOTE: Errors thrown by the error handler itself must not be given back
+            // to it, or it may never stop, and errors thrown by the main thread must
+            // not be given to it either, since the script would then exit successfully
+            let is_handler_thread = errors.handler_threads.remove(&thread_id);
+            let is_main_thread = errors.main_threads.remove(&thread_id);
+            let handler = match (&errors.handler, is_handler_thread || is_main_thread) {
+                (Some(key), false) => lua.registry_value::<LuaFunction>(key).ok(),
+                _ => None,
+            };
+            (errors.policy, origin, handler)
+        };
+
+        if let Some(handler) = handler {
+            match self.push_error_handler(lua, handler, thread, &err, origin.as_deref()) {
+                Ok(()) => return,
+                Err(e) => lua.emit_error(e),
+            }
+        }
+
+        self.state.increment_error_count();
+        lua.emit_thread_error(err, origin.as_deref());
+        if policy == ErrorPolicy::Abort && !self.state.has_exit_code() {
+            self.state.set_exit_code(1);
+        }
+    }
+
+    fn push_error_handler<'lua>(
+        &self,
+        lua: &'lua Lua,
+        handler: LuaFunction<'lua>,
+        thread: LuaThread<'lua>,
+        err: &LuaError,
+        origin: Option<&str>,
+    ) -> LuaResult<()> {
+        let (message, traceback) = split_error(err);
+        let info = TableBuilder::new(lua)?
+            .with_value("thread", thread)?
+            .with_value("traceback", traceback)?
+            .with_value("origin", origin)?
+            .build_readonly()?;
+
+        let handler_thread = lua.create_thread(handler)?;
+        let handler_thread_id = self.push_back(lua, handler_thread, (message, info))?;
+        self.errors
+            .lock()
+            .expect("Failed to lock scheduler errors")
+            .handler_threads
+            .insert(handler_thread_id);
+        Ok(())
+    }
+}
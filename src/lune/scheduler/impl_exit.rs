@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use mlua::prelude::*;
+use tracing::debug;
+
+use crate::lune::util::traits::LuaEmitErrorExt;
+
+use super::{state::SchedulerState, Scheduler};
+
+/**
+    Lua functions to call once the scheduler is about to exit.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SchedulerExitHooks {
+    next_id: usize,
+    hooks: Vec<(usize, LuaRegistryKey)>,
+}
+
+/**
+    Creates the error that is used to stop lua threads from
+    running any further once an exit code has been set.
+*/
+pub(super) fn exit_error() -> LuaError {
+    LuaError::runtime("Process is exiting")
+}
+
+/**
+    A handle that can be used to make a scheduler exit from any thread,
+    stopping any lua threads that are currently running as soon as possible.
+*/
+#[derive(Debug, Clone)]
+pub struct SchedulerExitHandle(Arc<SchedulerState>);
+
+impl SchedulerExitHandle {
+    /**
+        Sets the exit code for the scheduler, unless one has already been set.
+    */
+    pub fn exit(&self, code: u8) {
+        if !self.0.has_exit_code() {
+            self.0.set_exit_code(code);
+        }
+    }
+}
+
+impl<'fut> Scheduler<'fut> {
+    /**
+        Creates a new handle that can be used to make this scheduler exit from any thread.
+    */
+    pub fn exit_handle(&self) -> SchedulerExitHandle {
+        SchedulerExitHandle(Arc::clone(&self.state))
+    }
+
+    /**
+        Adds a hook that will be called with the final exit code once the
+        scheduler is about to exit, returning an id that can be used to remove it.
+
+        Hooks are called in the order they were added, and each hook runs to
+        completion as a separate lua thread before the next one is called.
+    */
+    pub fn add_exit_hook<'lua>(&self, lua: &'lua Lua, hook: LuaFunction<'lua>) -> LuaResult<usize> {
+        let key = lua.create_registry_value(hook)?;
+        let mut exit_hooks = self.exit_hooks.lock().expect("Failed to lock exit hooks");
+        let id = exit_hooks.next_id;
+        exit_hooks.next_id += 1;
+        exit_hooks.hooks.push((id, key));
+        Ok(id)
+    }
+
+    /**
+        Removes the exit hook with the given id, if it has not yet been called.
+    */
+    pub fn remove_exit_hook(&self, lua: &Lua, id: usize) -> LuaResult<()> {
+        let key = {
+            let mut exit_hooks = self.exit_hooks.lock().expect("Failed to lock exit hooks");
+            exit_hooks
+                .hooks
+                .iter()
+                .position(|(i, _)| *i == id)
+                .map(|index| exit_hooks.hooks.remove(index).1)
+        };
+        if let Some(key) = key {
+            lua.remove_registry_value(key)?;
+        }
+        Ok(())
+    }
+
+    /**
+        Checks if the exit hook with the given id has not yet been removed or called.
+    */
+    pub fn has_exit_hook(&self, id: usize) -> bool {
+        self.exit_hooks
+            .lock()
+            .expect("Failed to lock exit hooks")
+            .hooks
+            .iter()
+            .any(|(i, _)| *i == id)
+    }
+
+    pub(super) fn has_exit_hooks(&self) -> bool {
+        !self
+            .exit_hooks
+            .lock()
+            .expect("Failed to lock exit hooks")
+            .hooks
+            .is_empty()
+    }
+
+    fn pop_exit_hook<'lua>(&self, lua: &'lua Lua) -> LuaResult<Option<LuaFunction<'lua>>> {
+        let key = {
+            let mut exit_hooks = self.exit_hooks.lock().expect("Failed to lock exit hooks");
+            match exit_hooks.hooks.is_empty() {
+                true => None,
+                false => Some(exit_hooks.hooks.remove(0).1),
+            }
+        };
+        match key {
+            None => Ok(None),
+            Some(key) => {
+                let hook = lua.registry_value(&key)?;
+                lua.remove_registry_value(key)?;
+                Ok(Some(hook))
+            }
+        }
+    }
+
+    /**
+        Runs all exit hooks to completion, calling them with the given exit code.
+
+        Any lua threads that were still queued, or waiting for a lua future to resume
+        them, are discarded before the first hook runs. Background futures keep being
+        resumed, and exit hooks may yield, for example to stop a running web server.
+
+        Returns the new exit code if it was changed by an exit hook.
+    */
+    pub(super) async fn run_exit_hooks(&self, lua: &Lua, code: u8) -> Option<u8> {
+        let mut new_code = None;
+        let mut count = 0;
+
+        self.threads
+            .try_lock()
+            .expect("Failed to lock threads vec")
+            .clear();
+        self.futures_lua
+            .try_lock()
+            .expect("Failed to lock lua futures for exit hooks")
+            .clear();
+
+        while let Some(hook) = self
+            .pop_exit_hook(lua)
+            .expect("Failed to get exit hook from registry")
+        {
+            let thread = lua
+                .create_thread(hook)
+                .expect("Failed to create thread for exit hook");
+            self.push_back(lua, thread.clone(), new_code.unwrap_or(code))
+                .expect("Failed to push exit hook thread to scheduler");
+
+            loop {
+                self.run_lua_threads(lua);
+                if thread.status() != LuaThreadStatus::Resumable || self.state.has_exit_code() {
+                    break;
+                }
+                if self.run_futures().await {
+                    lua.emit_error(self.deadlock_error(lua));
+                    break;
+                }
+                if thread.status() != LuaThreadStatus::Resumable || self.state.has_exit_code() {
+                    break;
+                }
+                // NOTE: The hook may have yielded without anything left to resume it
+                let (has_future_lua, has_future_background) = self.has_futures();
+                if !has_future_lua && !has_future_background && !self.has_thread() {
+                    break;
+                }
+            }
+
+            // An exit hook may also exit, which changes the exit code,
+            // and this should not stop any other exit hooks from running
+            if let Some(code) = self.state.exit_code() {
+                new_code = Some(code);
+                self.state.clear_exit_code();
+            }
+
+            count += 1;
+        }
+
+        if count > 0 {
+            debug! {
+                %count,
+                "ran exit hooks"
+            }
+        }
+
+        new_code
+    }
+}
@@ -0,0 +1,186 @@
+use std::{fmt::Write, sync::Arc};
+
+use futures_util::{future, Future};
+use mlua::prelude::*;
+
+use crate::lune::util::{formatting::pretty_format_luau_traceback, TableBuilder};
+
+use super::{
+    inspect::{SchedulerFutureKind, SchedulerParked},
+    Scheduler,
+};
+
+/**
+    A lua thread that is currently alive in the scheduler.
+*/
+struct InspectedThread {
+    status: &'static str,
+    origin: Option<String>,
+    traceback: String,
+}
+
+fn get_traceback(lua: &Lua, thread: LuaThread) -> LuaResult<String> {
+    let traceback = lua
+        .globals()
+        .get::<_, LuaTable>("debug")?
+        .get::<_, LuaFunction>("traceback")?
+        .call::<_, String>(thread)?;
+    Ok(pretty_format_luau_traceback(&traceback))
+}
+
+impl<'fut> Scheduler<'fut> {
+    /**
+        Parks the given future, marking it as a future that can only be woken up by other lua threads.
+
+        This should be used for any future that waits on state shared between lua threads, such
+        as a channel or a mutex, so that the scheduler can detect when all threads are waiting
+        for each other, and would otherwise keep waiting forever.
+    */
+    pub fn park<F: Future>(&self, fut: F) -> SchedulerParked<F> {
+        SchedulerParked::new(Arc::clone(&self.state), fut)
+    }
+
+    /**
+        Checks if the scheduler is deadlocked, meaning that no lua threads are ready to
+        run, and all lua threads that are waiting are parked, with nothing else left
+        that could ever wake them up again.
+
+        Detached futures are not taken into account here, since they do not keep the
+        scheduler alive, and may never complete - the scheduler would otherwise wait
+        forever, for example whenever a signal handler has been connected.
+    */
+    pub(super) fn is_deadlocked(&self) -> bool {
+        let infos = self
+            .futures_info
+            .lock()
+            .expect("Failed to lock futures info");
+        let num_lua = infos.count(SchedulerFutureKind::Lua);
+        num_lua > 0
+            && num_lua == self.state.num_parked_futures()
+            && infos.count(SchedulerFutureKind::Background) == 0
+            && !self.has_thread()
+    }
+
+    /**
+        Waits until the scheduler is deadlocked, once all other
+        futures have had a chance to make progress first.
+
+        This future never completes if the scheduler is not deadlocked.
+    */
+    pub(super) async fn wait_for_deadlock(&self) {
+        tokio::task::yield_now().await;
+        if !self.is_deadlocked() {
+            future::pending::<()>().await;
+        }
+    }
+
+    fn inspect_threads(&self, lua: &Lua) -> LuaResult<Vec<InspectedThread>> {
+        let mut threads = Vec::new();
+
+        let queued = self
+            .threads
+            .try_lock()
+            .expect("Failed to lock threads vec")
+            .iter()
+            .map(|thread| thread.thread(lua))
+            .collect::<Vec<_>>();
+        for thread in queued {
+            threads.push(InspectedThread {
+                status: "ready",
+                origin: None,
+                traceback: get_traceback(lua, thread)?,
+            });
+        }
+
+        let waiting = self
+            .futures_info
+            .lock()
+            .expect("Failed to lock futures info")
+            .iter()
+            .filter_map(|info| {
+                let (_, key) = info.thread.as_ref()?;
+                Some((info.origin.clone(), lua.registry_value::<LuaThread>(key)))
+            })
+            .collect::<Vec<_>>();
+        for (origin, thread) in waiting {
+            threads.push(InspectedThread {
+                status: "waiting",
+                origin: Some(origin),
+                traceback: get_traceback(lua, thread?)?,
+            });
+        }
+
+        Ok(threads)
+    }
+
+    /**
+        Creates a table describing all lua threads that are currently alive
+        in the scheduler, and all futures that are currently spawned on it.
+
+        This must be called from a running lua thread, which will be listed first.
+    */
+    pub fn inspect<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaTable<'lua>> {
+        let mut threads = vec![InspectedThread {
+            status: "running",
+            origin: None,
+            traceback: get_traceback(lua, lua.current_thread())?,
+        }];
+        threads.extend(self.inspect_threads(lua)?);
+
+        let threads = threads
+            .into_iter()
+            .map(|thread| {
+                TableBuilder::new(lua)?
+                    .with_value("status", thread.status)?
+                    .with_value("origin", thread.origin)?
+                    .with_value("traceback", thread.traceback)?
+                    .build_readonly()
+            })
+            .collect::<LuaResult<Vec<_>>>()?;
+
+        let futures = self
+            .futures_info
+            .lock()
+            .expect("Failed to lock futures info")
+            .iter()
+            .map(|info| (info.kind, info.origin.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|(kind, origin)| {
+                TableBuilder::new(lua)?
+                    .with_value("kind", kind.as_str())?
+                    .with_value("origin", origin)?
+                    .build_readonly()
+            })
+            .collect::<LuaResult<Vec<_>>>()?;
+
+        TableBuilder::new(lua)?
+            .with_value("threads", lua.create_sequence_from(threads)?)?
+            .with_value("futures", lua.create_sequence_from(futures)?)?
+            .build_readonly()
+    }
+
+    /**
+        Creates an error describing a deadlock, listing all of the lua threads that are waiting forever.
+    */
+    pub(super) fn deadlock_error(&self, lua: &Lua) -> LuaError {
+        let threads = match self.inspect_threads(lua) {
+            Err(e) => return e,
+            Ok(threads) => threads,
+        };
+        // NOTE: This does not use the usual stack begin & end markers, since
+        // error formatting only supports a single stack trace per error
+        let mut message = format!(
+            "Deadlock detected - {} thread(s) are waiting for each other, and can never be resumed",
+            threads.len()
+        );
+        for thread in threads {
+            match thread.origin {
+                Some(origin) => write!(message, "\n\nThread waiting at {origin}").unwrap(),
+                None => write!(message, "\n\nThread {}", thread.status).unwrap(),
+            }
+            write!(message, "\n{}", thread.traceback).unwrap();
+        }
+        LuaError::RuntimeError(message)
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    pin::pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::poll;
+use mlua::prelude::*;
+
+use super::{Scheduler, SchedulerThreadId};
+
+/**
+    What the scheduler should do when a lua thread runs for
+    longer than the time limit, without yielding on its own.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeLimitAction {
+    /**
+        Throws an error in the lua thread, which may be caught using `pcall`.
+    */
+    #[default]
+    Error,
+    /**
+        Forces the lua thread to yield, and resumes it again once all
+        other lua threads and futures have had a chance to run.
+    */
+    Yield,
+}
+
+impl FromStr for TimeLimitAction {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "yield" => Ok(Self::Yield),
+            _ => Err(format!(
+                "Invalid time limit action '{s}' - expected 'error' or 'yield'"
+            )),
+        }
+    }
+}
+
+/**
+    State used by the scheduler to limit how long lua threads may run without yielding.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SchedulerTimeLimit {
+    enabled: AtomicBool,
+    limit: Mutex<Option<(Duration, TimeLimitAction)>>,
+    resumed_at: Mutex<Option<Instant>>,
+    preempted: AtomicBool,
+}
+
+impl SchedulerTimeLimit {
+    /**
+        Starts measuring time for a lua thread that is about to be resumed.
+    */
+    pub(super) fn start(&self) {
+        if self.enabled.load(Ordering::Relaxed) {
+            *self.resumed_at.lock().expect("Failed to lock time limit") = Some(Instant::now());
+        }
+    }
+
+    /**
+        Stops measuring time for the lua thread that was last resumed, and
+        returns `true` if it was forced to yield because of the time limit.
+    */
+    pub(super) fn stop(&self) -> bool {
+        if self.enabled.load(Ordering::Relaxed) {
+            *self.resumed_at.lock().expect("Failed to lock time limit") = None;
+        }
+        self.preempted.swap(false, Ordering::SeqCst)
+    }
+
+    /**
+        Checks if the given lua thread has run for longer than the time limit,
+        and if it has, either returns an error or tells the lua VM to yield.
+
+        Meant to be called from the luau interrupt.
+    */
+    pub(super) fn check(&self, lua: &Lua, thread_id: SchedulerThreadId) -> LuaResult<LuaVmState> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return Ok(LuaVmState::Continue);
+        }
+
+        // NOTE: If the thread is still running after we told it to yield, it was not
+        // able to yield, such as when running inside of a metamethod, and the
+        // error thrown by the VM for this may have been caught using pcall
+        self.preempted.store(false, Ordering::SeqCst);
+
+        let Some((limit, action)) = *self.limit.lock().expect("Failed to lock time limit") else {
+            return Ok(LuaVmState::Continue);
+        };
+        let mut resumed_at = self.resumed_at.lock().expect("Failed to lock time limit");
+        match *resumed_at {
+            Some(started) if started.elapsed() >= limit => {}
+            _ => return Ok(LuaVmState::Continue),
+        }
+
+        match action {
+            TimeLimitAction::Error => {
+                // Any code that catches the error gets a new time limit, since
+                // erroring here again right away would make it impossible to handle
+                *resumed_at = Some(Instant::now());
+                Err(LuaError::RuntimeError(format!(
+                    "Thread ran for longer than the time limit of {}s without yielding",
+                    limit.as_secs_f64()
+                )))
+            }
+            TimeLimitAction::Yield => {
+                // NOTE: Only threads resumed by the scheduler may be preempted, yielding
+                // any other coroutine would give control back to the lua code resuming it
+                if SchedulerThreadId::from(&lua.current_thread()) != thread_id {
+                    return Ok(LuaVmState::Continue);
+                }
+                *resumed_at = Some(Instant::now());
+                self.preempted.store(true, Ordering::SeqCst);
+                Ok(LuaVmState::Yield)
+            }
+        }
+    }
+}
+
+impl<'fut> Scheduler<'fut> {
+    /**
+        Sets the maximum amount of time that a lua thread may run for each time it
+        is resumed, and what to do with lua threads that run for longer than that.
+    */
+    pub fn set_time_limit(&self, limit: Duration, action: TimeLimitAction) {
+        *self
+            .time_limit
+            .limit
+            .lock()
+            .expect("Failed to lock time limit") = Some((limit, action));
+        self.time_limit.enabled.store(true, Ordering::SeqCst);
+    }
+
+    /**
+        Polls all queued futures once, without waiting for any of them to complete.
+
+        Used to let futures make progress between resumptions of
+        lua threads that were forced to yield by the time limit.
+    */
+    pub(super) async fn poll_futures(&self) {
+        tokio::task::yield_now().await;
+        let (has_lua, has_background) = self.has_futures();
+        if has_lua {
+            _ = poll!(pin!(self.run_future_lua()));
+        }
+        if has_background || self.has_detached_futures() {
+            _ = poll!(pin!(self.run_future_background()));
+        }
+    }
+}
@@ -13,13 +13,17 @@ use super::Scheduler;
 impl<'fut> Scheduler<'fut> {
     /**
         Runs all lua threads to completion.
+
+        Returns `true` if resumption stopped early because a lua thread was forced
+        to yield by the time limit, and futures should be given a chance to run.
     */
-    fn run_lua_threads(&self, lua: &Lua) {
+    pub(super) fn run_lua_threads(&self, lua: &Lua) -> bool {
         if self.state.has_exit_code() {
-            return;
+            return false;
         }
 
         let mut count = 0;
+        let mut preempted = false;
 
         // Pop threads from the scheduler until there are none left
         while let Some(thread) = self
@@ -39,22 +43,29 @@ impl<'fut> Scheduler<'fut> {
             // Resume the thread, ensuring that the schedulers
             // current thread id is set correctly for error catching
             self.state.set_current_thread_id(Some(thread_id));
+            self.time_limit.start();
             let res = thread.resume::<_, LuaMultiValue>(args);
+            let forced_yield = self.time_limit.stop();
             self.state.set_current_thread_id(None);
 
             count += 1;
 
-            // If we got any resumption (lua-side) error, increment
-            // the error count of the scheduler so we can exit with
-            // a non-zero exit code, and print it out to stderr
+            // NOTE: A thread that errored was not able to yield, even if it was told to
+            let forced_yield = forced_yield && res.is_ok();
+
+            // If we got any resumption (lua-side) error, give it to the error
+            // handler, or emit it and follow the error policy of the scheduler -
+            // unless the error was caused by exiting, which is not a real error
             if let Err(err) = &res {
-                self.state.increment_error_count();
-                lua.emit_error(err.clone());
+                if !self.state.has_exit_code() {
+                    self.handle_thread_error(lua, thread.clone(), thread_id, err.clone());
+                }
             }
 
             // If the thread has finished running completely,
             // send results of final resume to any listeners
             if thread.status() != LuaThreadStatus::Resumable {
+                self.forget_thread_errors(thread_id);
                 // NOTE: Threads that were spawned to resume
                 // with an error will not have a result sender
                 if let Some(sender) = self
@@ -80,6 +91,16 @@ impl<'fut> Scheduler<'fut> {
             if self.state.has_exit_code() {
                 break;
             }
+
+            // If the thread was forced to yield because it ran for longer
+            // than the time limit, it should be resumed again later on, but
+            // not before other threads and futures have had a chance to run
+            if forced_yield && thread.status() == LuaThreadStatus::Resumable {
+                self.push_back(lua, thread, ())
+                    .expect("Failed to push preempted thread to scheduler");
+                preempted = true;
+                break;
+            }
         }
 
         if count > 0 {
@@ -88,6 +109,8 @@ impl<'fut> Scheduler<'fut> {
                 "resumed lua"
             }
         }
+
+        preempted
     }
 
     /**
@@ -95,7 +118,7 @@ impl<'fut> Scheduler<'fut> {
 
         Panics if no lua future is queued.
     */
-    async fn run_future_lua(&self) {
+    pub(super) async fn run_future_lua(&self) {
         let mut futs = self
             .futures_lua
             .try_lock()
@@ -109,11 +132,12 @@ impl<'fut> Scheduler<'fut> {
 
         Panics if no background future is queued.
     */
-    async fn run_future_background(&self) {
+    pub(super) async fn run_future_background(&self) {
         let mut futs = self
             .futures_background
             .try_lock()
             .expect("Failed to lock background futures for resumption");
+        self.queue_pending_background_futures(&futs);
         assert!(futs.len() > 0, "No background futures are queued");
         futs.next().await;
     }
@@ -145,21 +169,38 @@ impl<'fut> Scheduler<'fut> {
         We need to listen for both future queues concurrently,
         and break out whenever the other corresponding queue has
         a new future, since the other queue may resume sooner.
+
+        Returns `true` if resumption stopped because the scheduler
+        is deadlocked, and no futures will ever be able to complete.
     */
-    async fn run_futures(&self) {
+    pub(super) async fn run_futures(&self) -> bool {
         let (mut has_lua, mut has_background) = self.has_futures();
         if !has_lua && !has_background {
-            return;
+            return false;
         }
 
         let mut rx = self.state.message_receiver();
         let mut count = 0;
+        let mut deadlocked = false;
 
         while has_lua || has_background {
-            if has_lua && has_background {
+            // NOTE: Detached futures do not keep the scheduler alive, but
+            // must still be resumed while we wait for any other futures
+            let poll_background = has_background || self.has_detached_futures();
+            // NOTE: When using a virtual clock, any futures waiting for it would never complete
+            // on their own, so it should be advanced once no other futures can make progress
+            let advance_clock = self.has_virtual_timers();
+            if has_lua && poll_background {
                 tokio::select! {
                     _ = self.run_future_lua() => {},
                     _ = self.run_future_background() => {},
+                    _ = self.advance_clock_when_idle(), if advance_clock => {},
+                    // NOTE: Only detached futures may be left in the background
+                    // here, and those should never stop us from detecting deadlocks
+                    _ = self.wait_for_deadlock(), if !has_background => {
+                        deadlocked = true;
+                        break;
+                    },
                     msg = rx.recv() => {
                         if let Some(msg) = msg {
                             if msg.should_break_futures() {
@@ -172,6 +213,11 @@ impl<'fut> Scheduler<'fut> {
             } else if has_lua {
                 tokio::select! {
                     _ = self.run_future_lua() => {},
+                    _ = self.advance_clock_when_idle(), if advance_clock => {},
+                    _ = self.wait_for_deadlock() => {
+                        deadlocked = true;
+                        break;
+                    },
                     msg = rx.recv() => {
                         if let Some(msg) = msg {
                             if msg.should_break_lua_futures() {
@@ -181,9 +227,10 @@ impl<'fut> Scheduler<'fut> {
                     }
                 }
                 count += 1;
-            } else if has_background {
+            } else if poll_background {
                 tokio::select! {
                     _ = self.run_future_background() => {},
+                    _ = self.advance_clock_when_idle(), if advance_clock => {},
                     msg = rx.recv() => {
                         if let Some(msg) = msg {
                             if msg.should_break_background_futures() {
@@ -203,6 +250,8 @@ impl<'fut> Scheduler<'fut> {
                 "resumed lua futures"
             }
         }
+
+        deadlocked
     }
 
     /**
@@ -222,7 +271,7 @@ impl<'fut> Scheduler<'fut> {
 
         loop {
             // 1. Run lua threads until exit or there are none left
-            self.run_lua_threads(lua);
+            let preempted = self.run_lua_threads(lua);
 
             // 2. If we got a manual exit code from lua we should
             // not try to wait for any pending futures to complete
@@ -233,14 +282,29 @@ impl<'fut> Scheduler<'fut> {
             // 3. Keep resuming futures until there are no futures left to
             // resume, or until we manually break out of resumption for any
             // reason, this may be because a future spawned a new lua thread
-            self.run_futures().await;
+            // NOTE: If a lua thread was forced to yield by the time limit, it is already
+            // queued again, and we should only let futures make some progress right now
+            let deadlocked = if preempted {
+                self.poll_futures().await;
+                false
+            } else {
+                self.run_futures().await
+            };
+
+            // 4. If all lua threads are waiting for each other, they will never
+            // be resumed, and we should report it instead of waiting forever
+            if deadlocked {
+                self.state.increment_error_count();
+                lua.emit_error(self.deadlock_error(lua));
+                break;
+            }
 
-            // 4. Once again, check for an exit code, in case a future sets one
+            // 5. Once again, check for an exit code, in case a future sets one
             if self.state.has_exit_code() {
                 break;
             }
 
-            // 5. If we have no lua threads or futures remaining,
+            // 6. If we have no lua threads or futures remaining,
             // we have now run the scheduler until completion
             let (has_future_lua, has_future_background) = self.has_futures();
             if !has_future_lua && !has_future_background && !self.has_thread() {
@@ -248,7 +312,21 @@ impl<'fut> Scheduler<'fut> {
             }
         }
 
-        if let Some(code) = self.state.exit_code() {
+        // 7. Run any exit hooks, these need the exit code to be cleared
+        // while running, and they may also set a new exit code themselves
+        let mut exit_code = self.state.exit_code();
+        if self.has_exit_hooks() {
+            let code = exit_code.unwrap_or(match self.state.has_errored() {
+                true => 1,
+                false => 0,
+            });
+            self.state.clear_exit_code();
+            if let Some(new_code) = self.run_exit_hooks(lua, code).await {
+                exit_code = Some(new_code);
+            }
+        }
+
+        if let Some(code) = exit_code {
             debug! {
                 %code,
                 "scheduler ran to completion"
@@ -144,6 +144,52 @@ impl<'fut> Scheduler<'fut> {
         Ok(thread_id)
     }
 
+    /**
+        Cancels the given `thread`, stopping it from being resumed again.
+
+        Any futures that would have resumed the thread are dropped, and anything
+        waiting for the thread to finish running will receive an error instead.
+    */
+    pub fn cancel_thread<'a>(&self, lua: &'a Lua, thread: LuaThread<'a>) -> LuaResult<()> {
+        let thread_id = SchedulerThreadId::from(&thread);
+
+        let close = lua
+            .globals()
+            .get::<_, LuaTable>("coroutine")?
+            .get::<_, LuaFunction>("close")?;
+        match close.call(thread) {
+            Err(LuaError::CoroutineInactive) | Ok(()) => {}
+            Err(e) => return Err(e),
+        }
+
+        self.thread_futures
+            .lock()
+            .expect("Failed to lock thread futures")
+            .abort_all(thread_id);
+        self.futures_info
+            .lock()
+            .expect("Failed to lock futures info")
+            .remove_thread(thread_id);
+        self.forget_thread_errors(thread_id);
+        self.state.get_thread_error(thread_id);
+
+        if let Some(sender) = self
+            .thread_senders
+            .try_lock()
+            .into_lua_err()
+            .context("Failed to lock thread senders vec")?
+            .remove(&thread_id)
+        {
+            if sender.receiver_count() > 0 {
+                sender
+                    .send(Err(LuaError::runtime("Thread was cancelled")))
+                    .expect("Failed to broadcast thread cancellation");
+            }
+        }
+
+        Ok(())
+    }
+
     /**
         Waits for the given thread to finish running, and returns its result.
     */
@@ -0,0 +1,254 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    panic::Location,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::task::{waker, ArcWake};
+use mlua::prelude::*;
+
+use super::{state::SchedulerState, SchedulerThreadId};
+
+/**
+    The kind of queue that a future was spawned on.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SchedulerFutureKind {
+    Lua,
+    Background,
+    Detached,
+}
+
+impl SchedulerFutureKind {
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            Self::Lua => "lua",
+            Self::Background => "background",
+            Self::Detached => "detached",
+        }
+    }
+}
+
+/**
+    Information about a future that has been spawned on the scheduler, used for diagnostics.
+*/
+#[derive(Debug)]
+pub(super) struct SchedulerFutureInfo {
+    pub(super) kind: SchedulerFutureKind,
+    pub(super) origin: String,
+    pub(super) thread: Option<(SchedulerThreadId, LuaRegistryKey)>,
+}
+
+impl SchedulerFutureInfo {
+    /**
+        Creates new future info, with the origin being the lua script that is currently
+        running, or the given location in the Lune source code if no script is running.
+    */
+    pub(super) fn new(lua: Option<&Lua>, kind: SchedulerFutureKind, location: &Location) -> Self {
+        let origin = lua
+            .and_then(lua_call_site)
+            .unwrap_or_else(|| format!("{}:{}", location.file(), location.line()));
+        Self {
+            kind,
+            origin,
+            thread: None,
+        }
+    }
+}
+
+/**
+    Names of chunks that are used internally by Lune, which are
+    implementation details and should never be shown as origins.
+*/
+const INTERNAL_CHUNK_NAMES: &[&str] = &["async", "require", "task.protected", "task.spawn"];
+
+/**
+    Finds the script name and line of the closest lua function on the call stack, if any.
+*/
+pub(super) fn lua_call_site(lua: &Lua) -> Option<String> {
+    let mut level = 0;
+    while let Some(debug) = lua.inspect_stack(level) {
+        level += 1;
+        let source = debug.source();
+        if source.what == "C" {
+            continue;
+        }
+        let Some(short_src) = source.short_src else {
+            continue;
+        };
+        let name = short_src
+            .trim_start_matches("[string \"")
+            .trim_end_matches("\"]");
+        if INTERNAL_CHUNK_NAMES.contains(&name) {
+            continue;
+        }
+        return Some(format!("{name}:{}", debug.curr_line()));
+    }
+    None
+}
+
+/**
+    Information about all futures that are currently spawned on the scheduler.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SchedulerFuturesInfo {
+    next_id: usize,
+    infos: BTreeMap<usize, SchedulerFutureInfo>,
+}
+
+impl SchedulerFuturesInfo {
+    /**
+        Adds information about a future, returning an id that should be used to remove it.
+    */
+    pub(super) fn insert(&mut self, info: SchedulerFutureInfo) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.infos.insert(id, info);
+        id
+    }
+
+    /**
+        Removes information about the future with the given id, if it still exists.
+    */
+    pub(super) fn remove(&mut self, id: usize) {
+        self.infos.remove(&id);
+    }
+
+    /**
+        Removes information about all futures that would resume the given thread.
+
+        Futures for a thread that was cancelled are dropped once they are next polled,
+        but they should stop being listed as soon as the thread has been cancelled.
+    */
+    pub(super) fn remove_thread(&mut self, thread_id: SchedulerThreadId) {
+        self.infos
+            .retain(|_, info| !matches!(&info.thread, Some((id, _)) if *id == thread_id));
+    }
+
+    /**
+        Counts the number of futures of the given kind.
+    */
+    pub(super) fn count(&self, kind: SchedulerFutureKind) -> usize {
+        self.infos.values().filter(|info| info.kind == kind).count()
+    }
+
+    /**
+        Iterates over information about all futures, in the order they were spawned.
+    */
+    pub(super) fn iter(&self) -> impl Iterator<Item = &SchedulerFutureInfo> {
+        self.infos.values()
+    }
+}
+
+/**
+    A guard that removes information about a future once it has been dropped,
+    which happens both when the future completes, and when it gets cancelled.
+*/
+pub(super) struct SchedulerFutureGuard {
+    infos: Arc<Mutex<SchedulerFuturesInfo>>,
+    id: usize,
+}
+
+impl SchedulerFutureGuard {
+    pub(super) fn new(infos: &Arc<Mutex<SchedulerFuturesInfo>>, info: SchedulerFutureInfo) -> Self {
+        let id = infos
+            .lock()
+            .expect("Failed to lock futures info")
+            .insert(info);
+        Self {
+            infos: Arc::clone(infos),
+            id,
+        }
+    }
+}
+
+impl Drop for SchedulerFutureGuard {
+    fn drop(&mut self) {
+        if let Ok(mut infos) = self.infos.lock() {
+            infos.remove(self.id);
+        }
+    }
+}
+
+/**
+    Waker for a parked future, which unparks it as soon as it gets woken up.
+*/
+struct SchedulerParkedWaker {
+    state: Arc<SchedulerState>,
+    parked: Arc<AtomicBool>,
+    inner: Waker,
+}
+
+impl ArcWake for SchedulerParkedWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if arc_self.parked.swap(false, Ordering::SeqCst) {
+            arc_self.state.decrement_parked_futures();
+        }
+        arc_self.inner.wake_by_ref();
+    }
+}
+
+/**
+    A future that can only be woken up by other lua threads, such as when
+    waiting for a value to be sent through a channel, or for a mutex to unlock.
+
+    While waiting, this future counts as parked for the scheduler, which makes it
+    possible to detect when all lua threads are waiting for each other forever.
+*/
+pub struct SchedulerParked<F> {
+    state: Arc<SchedulerState>,
+    parked: Arc<AtomicBool>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F> SchedulerParked<F> {
+    pub(super) fn new(state: Arc<SchedulerState>, inner: F) -> Self {
+        Self {
+            state,
+            parked: Arc::new(AtomicBool::new(false)),
+            inner: Box::pin(inner),
+        }
+    }
+
+    fn unpark(&self) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            self.state.decrement_parked_futures();
+        }
+    }
+}
+
+impl<F: Future> Future for SchedulerParked<F> {
+    type Output = F::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let parked_waker = waker(Arc::new(SchedulerParkedWaker {
+            state: Arc::clone(&self.state),
+            parked: Arc::clone(&self.parked),
+            inner: cx.waker().clone(),
+        }));
+        let mut parked_cx = Context::from_waker(&parked_waker);
+        // NOTE: We park before polling, since the inner future may wake
+        // itself up while being polled, which must unpark it right away
+        if !self.parked.swap(true, Ordering::SeqCst) {
+            self.state.increment_parked_futures();
+        }
+        match self.inner.as_mut().poll(&mut parked_cx) {
+            Poll::Ready(output) => {
+                self.unpark();
+                Poll::Ready(output)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F> Drop for SchedulerParked<F> {
+    fn drop(&mut self) {
+        self.unpark();
+    }
+}
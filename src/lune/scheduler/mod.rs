@@ -1,28 +1,42 @@
 use std::{
     collections::{HashMap, VecDeque},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use futures_util::{stream::FuturesUnordered, Future};
 use mlua::prelude::*;
 use tokio::sync::Mutex as AsyncMutex;
 
+mod inspect;
 mod message;
 mod state;
 mod thread;
 mod traits;
 
 mod impl_async;
+mod impl_clock;
+mod impl_errors;
+mod impl_exit;
+mod impl_inspect;
+mod impl_limits;
 mod impl_runner;
 mod impl_threads;
 
+pub use self::impl_errors::ErrorPolicy;
+pub use self::impl_exit::SchedulerExitHandle;
+pub use self::impl_limits::TimeLimitAction;
 pub use self::thread::SchedulerThreadId;
 pub use self::traits::*;
 
 use self::{
+    impl_clock::SchedulerClock,
+    impl_errors::SchedulerErrors,
+    impl_exit::{exit_error, SchedulerExitHooks},
+    impl_limits::SchedulerTimeLimit,
+    inspect::SchedulerFuturesInfo,
     state::SchedulerState,
-    thread::{SchedulerThread, SchedulerThreadSender},
+    thread::{SchedulerThread, SchedulerThreadFutures, SchedulerThreadSender},
 };
 
 type SchedulerFuture<'fut> = Pin<Box<dyn Future<Output = ()> + 'fut>>;
@@ -38,6 +52,7 @@ pub(crate) struct Scheduler<'fut> {
     state: Arc<SchedulerState>,
     threads: Arc<AsyncMutex<VecDeque<SchedulerThread>>>,
     thread_senders: Arc<AsyncMutex<HashMap<SchedulerThreadId, SchedulerThreadSender>>>,
+    thread_futures: Arc<Mutex<SchedulerThreadFutures>>,
     /*
         FUTURE: Get rid of these, let the tokio runtime handle running
         and resumption of futures completely, just use our scheduler
@@ -61,6 +76,12 @@ pub(crate) struct Scheduler<'fut> {
     */
     futures_lua: Arc<AsyncMutex<FuturesUnordered<SchedulerFuture<'fut>>>>,
     futures_background: Arc<AsyncMutex<FuturesUnordered<SchedulerFuture<'static>>>>,
+    futures_background_pending: Arc<Mutex<FuturesUnordered<SchedulerFuture<'static>>>>,
+    futures_info: Arc<Mutex<SchedulerFuturesInfo>>,
+    exit_hooks: Arc<Mutex<SchedulerExitHooks>>,
+    errors: Arc<Mutex<SchedulerErrors>>,
+    clock: Arc<SchedulerClock>,
+    time_limit: Arc<SchedulerTimeLimit>,
 }
 
 impl<'fut> Scheduler<'fut> {
@@ -73,8 +94,15 @@ impl<'fut> Scheduler<'fut> {
             state: Arc::new(SchedulerState::new()),
             threads: Arc::new(AsyncMutex::new(VecDeque::new())),
             thread_senders: Arc::new(AsyncMutex::new(HashMap::new())),
+            thread_futures: Arc::new(Mutex::new(SchedulerThreadFutures::default())),
             futures_lua: Arc::new(AsyncMutex::new(FuturesUnordered::new())),
             futures_background: Arc::new(AsyncMutex::new(FuturesUnordered::new())),
+            futures_background_pending: Arc::new(Mutex::new(FuturesUnordered::new())),
+            futures_info: Arc::new(Mutex::new(SchedulerFuturesInfo::default())),
+            exit_hooks: Arc::new(Mutex::new(SchedulerExitHooks::default())),
+            errors: Arc::new(Mutex::new(SchedulerErrors::default())),
+            clock: Arc::new(SchedulerClock::default()),
+            time_limit: Arc::new(SchedulerTimeLimit::default()),
         }
     }
 
@@ -82,17 +110,28 @@ impl<'fut> Scheduler<'fut> {
         Sets the luau interrupt for this scheduler.
 
         This will propagate errors from any lua-spawned
-        futures back to the lua threads that spawned them.
+        futures back to the lua threads that spawned them,
+        and stop lua threads from running once an exit code is set.
+
+        It will also stop lua threads that have been running for longer
+        than the time limit, if one has been set for this scheduler.
     */
     pub fn set_interrupt_for(&self, lua: &Lua) {
         // Propagate errors given to the scheduler back to their lua threads
         // FUTURE: Do profiling and anything else we need inside of this interrupt
         let state = self.state.clone();
-        lua.set_interrupt(move |_| {
+        let time_limit = self.time_limit.clone();
+        lua.set_interrupt(move |lua| {
             if let Some(id) = state.get_current_thread_id() {
                 if let Some(err) = state.get_thread_error(id) {
                     return Err(err);
                 }
+                // NOTE: Errors may be caught using pcall, so we keep
+                // erroring here until the lua thread has fully stopped
+                if state.has_exit_code() {
+                    return Err(exit_error());
+                }
+                return time_limit.check(lua, id);
             }
             Ok(LuaVmState::Continue)
         });
@@ -113,6 +152,28 @@ impl<'fut> Scheduler<'fut> {
         self.state.set_exit_code(code.into());
     }
 
+    /**
+        Sets the exit code for the scheduler, unless one has already been
+        set, and returns an error that stops the current lua thread.
+
+        Unlike yielding, this works in any context, including from metamethods
+        and other functions that lua threads can not yield across, and any
+        lua thread that catches the error is stopped by the scheduler interrupt.
+    */
+    pub fn exit(&self, code: impl Into<u8>) -> LuaError {
+        if !self.state.has_exit_code() {
+            self.state.set_exit_code(code.into());
+        }
+        exit_error()
+    }
+
+    /**
+        Checks if an exit code has been set for the scheduler.
+    */
+    pub fn has_exit_code(&self) -> bool {
+        self.state.has_exit_code()
+    }
+
     #[doc(hidden)]
     pub fn into_static(self) -> &'static Self {
         Box::leak(Box::new(self))
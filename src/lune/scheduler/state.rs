@@ -27,6 +27,8 @@ pub(crate) struct SchedulerState {
     exit_code: AtomicU8,
     num_resumptions: AtomicUsize,
     num_errors: AtomicUsize,
+    num_detached_futures: AtomicUsize,
+    num_parked_futures: AtomicUsize,
     thread_id: Arc<Mutex<Option<SchedulerThreadId>>>,
     thread_errors: Arc<Mutex<HashMap<SchedulerThreadId, LuaError>>>,
     pub(super) message_sender: Arc<Mutex<UnboundedSender<SchedulerMessage>>>,
@@ -45,6 +47,8 @@ impl SchedulerState {
             exit_code: AtomicU8::new(0),
             num_resumptions: AtomicUsize::new(0),
             num_errors: AtomicUsize::new(0),
+            num_detached_futures: AtomicUsize::new(0),
+            num_parked_futures: AtomicUsize::new(0),
             thread_id: Arc::new(Mutex::new(None)),
             thread_errors: Arc::new(Mutex::new(HashMap::new())),
             message_sender: Arc::new(Mutex::new(message_sender)),
@@ -72,6 +76,54 @@ impl SchedulerState {
         self.num_errors.load(Ordering::SeqCst) > 0
     }
 
+    /**
+        Increments the number of queued detached futures for the scheduler.
+
+        Detached futures do not keep the scheduler alive, and this
+        is used to check if any other futures are still queued.
+    */
+    pub fn increment_detached_futures(&self) {
+        self.num_detached_futures.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /**
+        Decrements the number of queued detached futures for the scheduler.
+    */
+    pub fn decrement_detached_futures(&self) {
+        self.num_detached_futures.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /**
+        Gets the number of queued detached futures for the scheduler.
+    */
+    pub fn num_detached_futures(&self) -> usize {
+        self.num_detached_futures.load(Ordering::SeqCst)
+    }
+
+    /**
+        Increments the number of parked futures for the scheduler.
+
+        Parked futures can only be woken up by other lua threads, and
+        this is used to check if all lua threads are waiting forever.
+    */
+    pub fn increment_parked_futures(&self) {
+        self.num_parked_futures.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /**
+        Decrements the number of parked futures for the scheduler.
+    */
+    pub fn decrement_parked_futures(&self) {
+        self.num_parked_futures.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /**
+        Gets the number of parked futures for the scheduler.
+    */
+    pub fn num_parked_futures(&self) -> usize {
+        self.num_parked_futures.load(Ordering::SeqCst)
+    }
+
     /**
         Gets the currently set exit code for the scheduler, if any.
     */
@@ -99,6 +151,17 @@ impl SchedulerState {
         self.message_sender().send_exit_code_set();
     }
 
+    /**
+        Clears the explicit exit code for the scheduler, letting
+        lua threads and futures be resumed again.
+
+        This is used to run exit hooks once an exit code has been set.
+    */
+    pub fn clear_exit_code(&self) {
+        self.exit_state.store(false, Ordering::SeqCst);
+        self.exit_code.store(0, Ordering::SeqCst);
+    }
+
     /**
         Gets the currently running lua scheduler thread id, if any.
     */
@@ -1,5 +1,6 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
+use futures_util::future::AbortHandle;
 use mlua::prelude::*;
 use tokio::sync::broadcast::Sender;
 
@@ -96,6 +97,14 @@ impl SchedulerThread {
         (thread, args)
     }
 
+    /**
+        Retrieves the inner thread from the container, without removing it.
+    */
+    pub(super) fn thread<'lua>(&self, lua: &'lua Lua) -> LuaThread<'lua> {
+        lua.registry_value(&self.key_thread)
+            .expect("Failed to get thread from registry")
+    }
+
     /**
         Retrieves the unique, randomly generated id for this scheduler thread.
     */
@@ -103,3 +112,47 @@ impl SchedulerThread {
         self.thread_id
     }
 }
+
+/**
+    Abort handles for lua futures that will resume a thread once they
+    complete, so that they can be dropped if the thread is cancelled.
+*/
+#[derive(Debug, Default)]
+pub(super) struct SchedulerThreadFutures {
+    next_id: usize,
+    handles: HashMap<usize, (SchedulerThreadId, AbortHandle)>,
+}
+
+impl SchedulerThreadFutures {
+    /**
+        Adds the abort handle for a future that will resume the given thread,
+        returning an id that should be used to remove it once it completes.
+    */
+    pub(super) fn insert(&mut self, thread_id: SchedulerThreadId, handle: AbortHandle) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handles.insert(id, (thread_id, handle));
+        id
+    }
+
+    /**
+        Removes the abort handle with the given id, if it still exists.
+    */
+    pub(super) fn remove(&mut self, id: usize) {
+        self.handles.remove(&id);
+    }
+
+    /**
+        Aborts all futures that would resume the given thread once they complete.
+    */
+    pub(super) fn abort_all(&mut self, thread_id: SchedulerThreadId) {
+        self.handles.retain(|_, (id, handle)| {
+            if *id == thread_id {
+                handle.abort();
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
@@ -1,4 +1,8 @@
-use std::fmt::Write;
+use std::{
+    cmp::{Ordering, Reverse},
+    ffi::c_void,
+    fmt::Write,
+};
 
 use console::{colors_enabled, set_colors_enabled, style, Style};
 use mlua::prelude::*;
@@ -6,7 +10,7 @@ use once_cell::sync::Lazy;
 
 const MAX_FORMAT_DEPTH: usize = 4;
 
-const INDENT: &str = "    ";
+const INDENT_WIDTH: usize = 4;
 
 pub const STYLE_RESET_STR: &str = "\x1b[0m";
 
@@ -49,7 +53,9 @@ pub fn format_label<S: AsRef<str>>(s: S) -> String {
 }
 
 pub fn format_style(style: Option<&'static Style>) -> String {
-    if cfg!(test) {
+    if cfg!(test) || !colors_enabled() {
+        // NOTE: Colors are disabled when stdout is not a terminal,
+        // or when the NO_COLOR environment variable has been set
         "".to_string()
     } else if let Some(style) = style {
         // HACK: We have no direct way of referencing the ansi color code
@@ -100,102 +106,330 @@ pub fn style_from_style_str<S: AsRef<str>>(s: S) -> LuaResult<Option<&'static St
     })
 }
 
-pub fn pretty_format_value(
-    buffer: &mut String,
-    value: &LuaValue,
-    depth: usize,
-) -> std::fmt::Result {
-    // TODO: Handle tables with cyclic references
-    match &value {
-        LuaValue::Nil => write!(buffer, "nil")?,
-        LuaValue::Boolean(true) => write!(buffer, "{}", COLOR_YELLOW.apply_to("true"))?,
-        LuaValue::Boolean(false) => write!(buffer, "{}", COLOR_YELLOW.apply_to("false"))?,
-        LuaValue::Number(n) => write!(buffer, "{}", COLOR_CYAN.apply_to(format!("{n}")))?,
-        LuaValue::Integer(i) => write!(buffer, "{}", COLOR_CYAN.apply_to(format!("{i}")))?,
-        LuaValue::String(s) => write!(
-            buffer,
-            "\"{}\"",
-            COLOR_GREEN.apply_to(
-                s.to_string_lossy()
+/**
+    Options for pretty formatting lua values.
+
+    The defaults for these options are stored in lua app data, and used by `print` and
+    `warn`, as well as by `stdio.format` whenever no options are given to it directly.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub depth: usize,
+    pub indent: usize,
+    pub colors: bool,
+    pub sort_keys: bool,
+    pub max_items: Option<usize>,
+    pub multiline: bool,
+}
+
+impl FormatOptions {
+    const KEYS: [&'static str; 6] = [
+        "depth",
+        "indent",
+        "colors",
+        "sortKeys",
+        "maxItems",
+        "multiline",
+    ];
+
+    /**
+        Gets the default format options that have been set for the given lua state.
+    */
+    pub fn from_app_data(lua: &Lua) -> Self {
+        lua.app_data_ref::<Self>()
+            .map(|options| *options)
+            .unwrap_or_default()
+    }
+
+    /**
+        Creates a copy of these options, with any values
+        that are present in the given table overridden.
+    */
+    pub fn with_table(mut self, table: &LuaTable) -> LuaResult<Self> {
+        for pair in table.clone().pairs::<String, LuaValue>() {
+            let (key, _) = pair?;
+            if !Self::KEYS.contains(&key.as_str()) {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid format option '{key}', valid options are: {}",
+                    Self::KEYS.join(", ")
+                )));
+            }
+        }
+        if let Some(depth) = table.get("depth")? {
+            self.depth = depth;
+        }
+        if let Some(indent) = table.get("indent")? {
+            self.indent = indent;
+        }
+        if let Some(colors) = table.get("colors")? {
+            self.colors = colors;
+        }
+        if let Some(sort_keys) = table.get("sortKeys")? {
+            self.sort_keys = sort_keys;
+        }
+        if let Some(max_items) = table.get("maxItems")? {
+            self.max_items = Some(max_items);
+        }
+        if let Some(multiline) = table.get("multiline")? {
+            self.multiline = multiline;
+        }
+        Ok(self)
+    }
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            depth: MAX_FORMAT_DEPTH,
+            indent: INDENT_WIDTH,
+            colors: true,
+            sort_keys: false,
+            max_items: None,
+            multiline: true,
+        }
+    }
+}
+
+/**
+    Formatter for lua values, which keeps track of the tables that
+    are currently being formatted, so that cycles can be detected.
+
+    A table that is part of a cycle is prefixed with a reference
+    marker, which is then used wherever the cycle is found again.
+*/
+struct ValueFormatter<'a> {
+    options: &'a FormatOptions,
+    buffer: String,
+    ancestors: Vec<FormatAncestor>,
+    references: Vec<(usize, usize)>,
+    next_reference: usize,
+}
+
+struct FormatAncestor {
+    pointer: *const c_void,
+    position: usize,
+    reference: Option<usize>,
+}
+
+impl<'a> ValueFormatter<'a> {
+    fn new(options: &'a FormatOptions) -> Self {
+        Self {
+            options,
+            buffer: String::new(),
+            ancestors: Vec::new(),
+            references: Vec::new(),
+            next_reference: 0,
+        }
+    }
+
+    fn paint(&self, style: &Style, text: impl std::fmt::Display) -> String {
+        if self.options.colors {
+            style.apply_to(text).to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn write_painted(&mut self, style: &Style, text: impl std::fmt::Display) {
+        let painted = self.paint(style, text);
+        self.buffer.push_str(&painted);
+    }
+
+    fn finish(mut self) -> String {
+        // NOTE: Inserting from the back keeps the positions of
+        // any references earlier in the buffer valid, and since
+        // tables are closed inside out we need to sort them first
+        self.references
+            .sort_by_key(|&(position, _)| Reverse(position));
+        for (position, reference) in std::mem::take(&mut self.references) {
+            let marker = self.paint(&COLOR_PURPLE, format!("<ref *{reference}>"));
+            self.buffer.insert_str(position, &format!("{marker} "));
+        }
+        self.buffer
+    }
+
+    fn format_value(&mut self, value: &LuaValue, depth: usize) {
+        match &value {
+            LuaValue::Nil => self.buffer.push_str("nil"),
+            LuaValue::Boolean(true) => self.write_painted(&COLOR_YELLOW, "true"),
+            LuaValue::Boolean(false) => self.write_painted(&COLOR_YELLOW, "false"),
+            LuaValue::Number(n) => self.write_painted(&COLOR_CYAN, n),
+            LuaValue::Integer(i) => self.write_painted(&COLOR_CYAN, i),
+            LuaValue::String(s) => {
+                let escaped = s
+                    .to_string_lossy()
                     .replace('"', r#"\""#)
                     .replace('\r', r"\r")
-                    .replace('\n', r"\n")
-            )
-        )?,
-        LuaValue::Table(ref tab) => {
-            if depth >= MAX_FORMAT_DEPTH {
-                write!(buffer, "{}", STYLE_DIM.apply_to("{ ... }"))?;
-            } else if let Some(s) = call_table_tostring_metamethod(tab) {
-                write!(buffer, "{s}")?;
-            } else {
-                let mut is_empty = false;
-                let depth_indent = INDENT.repeat(depth);
-                write!(buffer, "{}", STYLE_DIM.apply_to("{"))?;
-                for pair in tab.clone().pairs::<LuaValue, LuaValue>() {
-                    let (key, value) = pair.unwrap();
-                    match &key {
-                        LuaValue::String(s) if can_be_plain_lua_table_key(s) => write!(
-                            buffer,
-                            "\n{}{}{} {} ",
-                            depth_indent,
-                            INDENT,
-                            s.to_string_lossy(),
-                            STYLE_DIM.apply_to("=")
-                        )?,
-                        _ => {
-                            write!(buffer, "\n{depth_indent}{INDENT}[")?;
-                            pretty_format_value(buffer, &key, depth)?;
-                            write!(buffer, "] {} ", STYLE_DIM.apply_to("="))?;
-                        }
+                    .replace('\n', r"\n");
+                let painted = self.paint(&COLOR_GREEN, escaped);
+                write!(self.buffer, "\"{painted}\"").unwrap();
+            }
+            LuaValue::Table(ref tab) => self.format_table(tab, depth),
+            LuaValue::Vector(v) => self.write_painted(
+                &COLOR_PURPLE,
+                format!("<vector({x}, {y}, {z})>", x = v.x(), y = v.y(), z = v.z()),
+            ),
+            LuaValue::Thread(_) => self.write_painted(&COLOR_PURPLE, "<thread>"),
+            LuaValue::Function(_) => self.write_painted(&COLOR_PURPLE, "<function>"),
+            LuaValue::UserData(u) => {
+                if let Some(s) = call_userdata_tostring_metamethod(u) {
+                    self.buffer.push_str(&s);
+                } else {
+                    self.write_painted(&COLOR_PURPLE, "<userdata>");
+                }
+            }
+            LuaValue::LightUserData(_) => self.write_painted(&COLOR_PURPLE, "<userdata>"),
+            LuaValue::Error(e) => self.buffer.push_str(&pretty_format_luau_error(e, false)),
+        }
+    }
+
+    fn format_table(&mut self, tab: &LuaTable, depth: usize) {
+        if depth >= self.options.depth {
+            self.write_painted(&STYLE_DIM, "{ ... }");
+            return;
+        }
+        if let Some(s) = call_table_tostring_metamethod(tab) {
+            self.buffer.push_str(&s);
+            return;
+        }
+
+        // If this table is already being formatted further up, we found a cycle,
+        // and should write a reference to it instead of formatting it again
+        let pointer = tab.to_pointer();
+        if let Some(ancestor) = self.ancestors.iter().position(|a| a.pointer == pointer) {
+            let reference = match self.ancestors[ancestor].reference {
+                Some(reference) => reference,
+                None => {
+                    self.next_reference += 1;
+                    self.ancestors[ancestor].reference = Some(self.next_reference);
+                    self.next_reference
+                }
+            };
+            self.write_painted(&COLOR_PURPLE, format!("<circular *{reference}>"));
+            return;
+        }
+        self.ancestors.push(FormatAncestor {
+            pointer,
+            position: self.buffer.len(),
+            reference: None,
+        });
+
+        let mut pairs = tab
+            .clone()
+            .pairs::<LuaValue, LuaValue>()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        if self.options.sort_keys {
+            pairs.sort_by(|(a, _), (b, _)| compare_table_keys(a, b));
+        }
+        let total = pairs.len();
+        let shown = self.options.max_items.map_or(total, |max| max.min(total));
+
+        let depth_indent = " ".repeat(self.options.indent * depth);
+        let item_indent = " ".repeat(self.options.indent * (depth + 1));
+
+        if total == 0 {
+            self.write_painted(&STYLE_DIM, "{}");
+        } else {
+            self.write_painted(&STYLE_DIM, "{");
+            for (index, (key, value)) in pairs.iter().take(shown).enumerate() {
+                if self.options.multiline {
+                    write!(self.buffer, "\n{item_indent}").unwrap();
+                } else if index > 0 {
+                    self.write_painted(&STYLE_DIM, ", ");
+                } else {
+                    self.buffer.push(' ');
+                }
+                match key {
+                    LuaValue::String(s) if can_be_plain_lua_table_key(s) => {
+                        self.buffer.push_str(&s.to_string_lossy());
+                    }
+                    _ => {
+                        self.buffer.push('[');
+                        self.format_value(key, depth);
+                        self.buffer.push(']');
                     }
-                    pretty_format_value(buffer, &value, depth + 1)?;
-                    write!(buffer, "{}", STYLE_DIM.apply_to(","))?;
-                    is_empty = false;
                 }
-                if is_empty {
-                    write!(buffer, "{}", STYLE_DIM.apply_to(" }"))?;
+                let equals = self.paint(&STYLE_DIM, "=");
+                write!(self.buffer, " {equals} ").unwrap();
+                self.format_value(value, depth + 1);
+                if self.options.multiline {
+                    self.write_painted(&STYLE_DIM, ",");
+                }
+            }
+            if shown < total {
+                let more = format!("... {} more", total - shown);
+                if self.options.multiline {
+                    write!(self.buffer, "\n{item_indent}").unwrap();
+                } else if shown > 0 {
+                    self.write_painted(&STYLE_DIM, ", ");
                 } else {
-                    write!(buffer, "\n{depth_indent}{}", STYLE_DIM.apply_to("}"))?;
+                    self.buffer.push(' ');
                 }
+                self.write_painted(&STYLE_DIM, more);
             }
-        }
-        LuaValue::Vector(v) => write!(
-            buffer,
-            "{}",
-            COLOR_PURPLE.apply_to(format!(
-                "<vector({x}, {y}, {z})>",
-                x = v.x(),
-                y = v.y(),
-                z = v.z()
-            ))
-        )?,
-        LuaValue::Thread(_) => write!(buffer, "{}", COLOR_PURPLE.apply_to("<thread>"))?,
-        LuaValue::Function(_) => write!(buffer, "{}", COLOR_PURPLE.apply_to("<function>"))?,
-        LuaValue::UserData(u) => {
-            if let Some(s) = call_userdata_tostring_metamethod(u) {
-                write!(buffer, "{s}")?
+            if self.options.multiline {
+                write!(self.buffer, "\n{depth_indent}").unwrap();
             } else {
-                write!(buffer, "{}", COLOR_PURPLE.apply_to("<userdata>"))?
+                self.buffer.push(' ');
             }
+            self.write_painted(&STYLE_DIM, "}");
+        }
+
+        let ancestor = self.ancestors.pop().expect("Missing table in ancestors");
+        if let Some(reference) = ancestor.reference {
+            self.references.push((ancestor.position, reference));
+        }
+    }
+}
+
+/**
+    Compares table keys for sorting, placing numbers first, then
+    strings, and then all other values in their original order.
+*/
+fn compare_table_keys(a: &LuaValue, b: &LuaValue) -> Ordering {
+    fn rank(value: &LuaValue) -> u8 {
+        match value {
+            LuaValue::Integer(_) | LuaValue::Number(_) => 0,
+            LuaValue::String(_) => 1,
+            _ => 2,
         }
-        LuaValue::LightUserData(_) => write!(buffer, "{}", COLOR_PURPLE.apply_to("<userdata>"))?,
-        LuaValue::Error(e) => write!(buffer, "{}", pretty_format_luau_error(e, false),)?,
     }
-    Ok(())
+    fn number(value: &LuaValue) -> f64 {
+        match value {
+            LuaValue::Integer(i) => *i as f64,
+            LuaValue::Number(n) => *n,
+            _ => 0.0,
+        }
+    }
+    match (a, b) {
+        (LuaValue::String(a), LuaValue::String(b)) => a.as_bytes().cmp(b.as_bytes()),
+        _ if rank(a) == 0 && rank(b) == 0 => number(a).total_cmp(&number(b)),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+pub fn pretty_format_value(value: &LuaValue, options: &FormatOptions) -> String {
+    let mut formatter = ValueFormatter::new(options);
+    formatter.format_value(value, 0);
+    formatter.finish()
 }
 
-pub fn pretty_format_multi_value(multi: &LuaMultiValue) -> LuaResult<String> {
+pub fn pretty_format_multi_value(
+    multi: &LuaMultiValue,
+    options: &FormatOptions,
+) -> LuaResult<String> {
     let mut buffer = String::new();
     let mut counter = 0;
     for value in multi {
         counter += 1;
         if let LuaValue::String(s) = value {
-            write!(buffer, "{}", s.to_string_lossy()).into_lua_err()?;
+            buffer.push_str(&s.to_string_lossy());
         } else {
-            pretty_format_value(&mut buffer, value, 0).into_lua_err()?;
+            buffer.push_str(&pretty_format_value(value, options));
         }
         if counter < multi.len() {
-            write!(&mut buffer, " ").into_lua_err()?;
+            buffer.push(' ');
         }
     }
     Ok(buffer)
@@ -362,6 +596,40 @@ pub fn pretty_format_luau_error(e: &LuaError, colorized: bool) -> String {
     }
 }
 
+/**
+    Formats a traceback from the luau `debug.traceback` function, using
+    the same friendly format as the stack traces for luau errors.
+*/
+pub fn pretty_format_luau_traceback(traceback: &str) -> String {
+    let lines = traceback
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (location, func_name) = match line.split_once(" function ") {
+                Some((location, func_name)) => (location, Some(func_name)),
+                None => (line, None),
+            };
+            let (name, line_num) = match location.rsplit_once("]:") {
+                Some((name, line_num)) => (name, Some(line_num)),
+                None => (location, None),
+            };
+            let name = match name.trim_start_matches('[').trim_end_matches(']') {
+                "C" => "[C]",
+                name => name.trim_start_matches("string \"").trim_end_matches('"'),
+            };
+            let mut result = format!("    Script '{name}'");
+            if let Some(line_num) = line_num {
+                write!(result, ", Line {line_num}").unwrap();
+            }
+            if let Some(func_name) = func_name {
+                write!(result, " - function {func_name}").unwrap();
+            }
+            result
+        })
+        .collect::<Vec<_>>();
+    fix_error_nitpicks(lines.join("\n"))
+}
+
 fn transform_stack_line(line: &str) -> String {
     match (line.find('['), line.find(']')) {
         (Some(idx_start), Some(idx_end)) => {
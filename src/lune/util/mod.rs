@@ -2,6 +2,7 @@ mod table_builder;
 
 pub mod formatting;
 pub mod futures;
+pub mod progress;
 pub mod traits;
 
 pub use table_builder::TableBuilder;
@@ -0,0 +1,74 @@
+use std::io::{self as std_io, Write};
+
+use indicatif::MultiProgress;
+use once_cell::sync::Lazy;
+use tokio::io::{self, AsyncWriteExt};
+
+/**
+    The progress bars and spinners that are currently being drawn to stderr.
+
+    All progress bars are part of the same [`MultiProgress`], which stacks
+    them on top of each other, and lets us temporarily clear them from the
+    terminal while writing any other output.
+
+    Note that progress bars are never drawn if stderr is not a terminal.
+*/
+static PROGRESS_BARS: Lazy<MultiProgress> = Lazy::new(MultiProgress::new);
+
+pub fn progress_bars() -> &'static MultiProgress {
+    &PROGRESS_BARS
+}
+
+fn progress_bars_visible() -> bool {
+    Lazy::get(&PROGRESS_BARS).is_some_and(|bars| !bars.is_hidden())
+}
+
+/**
+    Runs the given function with all progress bars cleared from the terminal,
+    drawing them again once it has finished, so that it can write to stdout or stderr.
+*/
+pub fn suspend_progress_bars<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    match Lazy::get(&PROGRESS_BARS) {
+        Some(bars) => bars.suspend(f),
+        None => f(),
+    }
+}
+
+/**
+    Writes the given bytes to stdout and flushes it,
+    without interfering with any visible progress bars.
+*/
+pub async fn write_stdout(bytes: &[u8]) -> io::Result<()> {
+    if progress_bars_visible() {
+        return suspend_progress_bars(|| {
+            let mut stdout = std_io::stdout().lock();
+            stdout.write_all(bytes)?;
+            stdout.flush()
+        });
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(bytes).await?;
+    stdout.flush().await?;
+    Ok(())
+}
+
+/**
+    Writes the given bytes to stderr and flushes it,
+    without interfering with any visible progress bars.
+*/
+pub async fn write_stderr(bytes: &[u8]) -> io::Result<()> {
+    if progress_bars_visible() {
+        return suspend_progress_bars(|| {
+            let mut stderr = std_io::stderr().lock();
+            stderr.write_all(bytes)?;
+            stderr.flush()
+        });
+    }
+    let mut stderr = io::stderr();
+    stderr.write_all(bytes).await?;
+    stderr.flush().await?;
+    Ok(())
+}
@@ -1,15 +1,35 @@
 use mlua::prelude::*;
 
-use super::formatting::format_label;
+use super::{
+    formatting::{format_label, STYLE_DIM},
+    progress::suspend_progress_bars,
+};
 use crate::LuneError;
 
 pub trait LuaEmitErrorExt {
     fn emit_error(&self, err: LuaError);
+    fn emit_thread_error(&self, err: LuaError, origin: Option<&str>);
 }
 
 impl LuaEmitErrorExt for Lua {
     fn emit_error(&self, err: LuaError) {
         // NOTE: LuneError will pretty-format this error
-        eprintln!("{}\n{}", format_label("error"), LuneError::from(err));
+        suspend_progress_bars(|| {
+            eprintln!("{}\n{}", format_label("error"), LuneError::from(err));
+        });
+    }
+
+    fn emit_thread_error(&self, err: LuaError, origin: Option<&str>) {
+        match origin {
+            None => self.emit_error(err),
+            Some(origin) => suspend_progress_bars(|| {
+                eprintln!(
+                    "{}\n{}\n{}",
+                    format_label("error"),
+                    LuneError::from(err),
+                    STYLE_DIM.apply_to(format!("Thread was spawned at {origin}"))
+                );
+            }),
+        }
     }
 }
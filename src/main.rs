@@ -8,14 +8,14 @@
     clippy::needless_pass_by_value
 )]
 
-use std::process::ExitCode;
+use std::{env, process::ExitCode};
 
 use clap::Parser;
 
 pub(crate) mod cli;
 
 use cli::Cli;
-use console::style;
+use console::{set_colors_enabled, set_colors_enabled_stderr, style};
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> ExitCode {
@@ -26,6 +26,11 @@ async fn main() -> ExitCode {
         .with_timer(tracing_subscriber::fmt::time::uptime())
         .with_level(true)
         .init();
+    // Respect the NO_COLOR environment variable, see https://no-color.org
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        set_colors_enabled(false);
+        set_colors_enabled_stderr(false);
+    }
     match Cli::parse().run().await {
         Ok(code) => code,
         Err(err) => {
@@ -1,11 +1,21 @@
-use std::process::ExitCode;
+use std::{env, path::Path, process::ExitCode, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use console::set_colors_enabled;
 use console::set_colors_enabled_stderr;
-use tokio::fs::read_to_string;
+use tokio::{
+    fs::read_to_string,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{
+        server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
 
-use crate::Lune;
+use crate::{ErrorPolicy, Lune, TimeLimitAction};
 
 const ARGS: &[&str] = &["Foo", "Bar"];
 
@@ -55,6 +65,8 @@ create_tests! {
     fs_metadata: "fs/metadata",
     fs_move: "fs/move",
 
+    log_levels: "log/levels",
+
     luau_compile: "luau/compile",
     luau_load: "luau/load",
     luau_options: "luau/options",
@@ -64,8 +76,14 @@ create_tests! {
     net_request_methods: "net/request/methods",
     net_request_query: "net/request/query",
     net_request_redirect: "net/request/redirect",
+    net_lookup_forward: "net/lookup/forward",
+    net_lookup_reverse: "net/lookup/reverse",
+    net_lookup_interfaces: "net/lookup/interfaces",
     net_url_encode: "net/url/encode",
     net_url_decode: "net/url/decode",
+    net_url_parse: "net/url/parse",
+    net_url_build: "net/url/build",
+    net_url_resolve: "net/url/resolve",
     net_serve_requests: "net/serve/requests",
     net_serve_websockets: "net/serve/websockets",
     net_socket_wss: "net/socket/wss",
@@ -75,12 +93,23 @@ create_tests! {
     process_cwd: "process/cwd",
     process_env: "process/env",
     process_exit: "process/exit",
+    process_on_exit: "process/onExit",
+    process_pipeline: "process/pipeline",
+    process_signal: "process/signal",
+    process_which: "process/which",
+    process_create_kill: "process/create/kill",
+    process_create_pty: "process/create/pty",
+    process_create_stdio: "process/create/stdio",
     process_spawn_async: "process/spawn/async",
     process_spawn_basic: "process/spawn/basic",
+    process_spawn_concurrent: "process/spawn/concurrent",
     process_spawn_cwd: "process/spawn/cwd",
+    process_spawn_detached: "process/spawn/detached",
     process_spawn_shell: "process/spawn/shell",
     process_spawn_stdin: "process/spawn/stdin",
     process_spawn_stdio: "process/spawn/stdio",
+    process_spawn_timeout: "process/spawn/timeout",
+    process_spawn_usage: "process/spawn/usage",
 
     require_async: "require/tests/async",
     require_async_background: "require/tests/async_background",
@@ -116,12 +145,225 @@ create_tests! {
     stdio_style: "stdio/style",
     stdio_write: "stdio/write",
     stdio_ewrite: "stdio/ewrite",
+    stdio_progress: "stdio/progress",
+    stdio_prompt_options: "stdio/promptOptions",
+    stdio_terminal: "stdio/terminal",
 
     task_cancel: "task/cancel",
+    task_combinators: "task/combinators",
     task_defer: "task/defer",
     task_delay: "task/delay",
+    task_inspect: "task/inspect",
+    task_on_error: "task/onError",
     task_spawn: "task/spawn",
+    task_sync: "task/sync",
     task_wait: "task/wait",
+    task_worker: "task/worker",
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn net_request_cassette() -> Result<ExitCode> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    // Record requests into a cassette file first, and then make
+    // sure that replaying gives back the exact same responses
+    let script_name = "tests/net/request/cassette";
+    let script = read_to_string(format!("{script_name}.luau")).await?;
+    let cassette = env::temp_dir().join(format!("lune-test-cassette-{}.json", std::process::id()));
+    let recorded = Lune::new()
+        .with_args(vec!["record".to_string()])
+        .with_net_recording(&cassette)
+        .run(script_name, &script)
+        .await?;
+    if recorded != ExitCode::SUCCESS {
+        return Ok(recorded);
+    }
+    let replayed = Lune::new()
+        .with_args(vec!["replay".to_string()])
+        .with_net_replay(&cassette)
+        .run(script_name, &script)
+        .await;
+    std::fs::remove_file(&cassette)?;
+    Ok(replayed?)
+}
+
+const TLS_FIXTURES: &str = "tests/net/request/tls";
+
+fn read_pem_certs(path: impl AsRef<Path>) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/**
+    Starts a local https server using the certificates in our tls fixtures,
+    which responds with a fixed body, and optionally requires clients to
+    present a certificate signed by the fixture certificate authority.
+*/
+async fn start_tls_server(port: u16, require_client_cert: bool) -> Result<()> {
+    let fixtures = Path::new(TLS_FIXTURES);
+    let certs = read_pem_certs(fixtures.join("server.pem"))?;
+    let key_pem = std::fs::read(fixtures.join("server.key"))?;
+    let key = rustls_pemfile::pkcs8_private_keys(&mut key_pem.as_slice())?
+        .pop()
+        .context("Missing server key")?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let config = if require_client_cert {
+        let mut roots = RootCertStore::empty();
+        for cert in read_pem_certs(fixtures.join("ca.pem"))? {
+            roots.add(&cert)?;
+        }
+        builder
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            .with_single_cert(certs, PrivateKey(key))?
+    } else {
+        builder
+            .with_no_client_auth()
+            .with_single_cert(certs, PrivateKey(key))?
+    };
+
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                // NOTE: Failed handshakes are expected here, since
+                // our test script also checks for rejected requests
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let body = "Hello, lune!";
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.ok();
+                stream.shutdown().await.ok();
+            });
+        }
+    });
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn net_request_tls() -> Result<ExitCode> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    start_tls_server(8084, false).await?;
+    start_tls_server(8085, true).await?;
+    let script_name = "tests/net/request/tls";
+    let script = read_to_string(format!("{script_name}.luau")).await?;
+    let exit_code = Lune::new().run(script_name, &script).await?;
+    Ok(exit_code)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_deadlock() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    // Deadlocked scripts should stop with an error instead of waiting forever
+    for script_name in ["tests/task/deadlock", "tests/task/deadlockDetached"] {
+        let script = read_to_string(format!("{script_name}.luau")).await?;
+        let exit_code = Lune::new().run(script_name, &script).await?;
+        assert_eq!(
+            exit_code,
+            ExitCode::FAILURE,
+            "{script_name} did not deadlock"
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_error_policy() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    let script_name = "tests/task/errorPolicy";
+    let script = read_to_string(format!("{script_name}.luau")).await?;
+    let continued = Lune::new()
+        .with_error_policy(ErrorPolicy::Continue)
+        .run(script_name, &script)
+        .await?;
+    assert_eq!(continued, ExitCode::SUCCESS);
+    let aborted = Lune::new()
+        .with_error_policy(ErrorPolicy::Abort)
+        .run(script_name, &script)
+        .await?;
+    assert_eq!(aborted, ExitCode::FAILURE);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_on_error_main() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    let script_name = "tests/task/onErrorMain";
+    let script = read_to_string(format!("{script_name}.luau")).await?;
+    let exit_code = Lune::new().run(script_name, &script).await?;
+    assert_eq!(exit_code, ExitCode::FAILURE);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_time_limit() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    for (script_name, action) in [
+        ("tests/task/timeLimitError", TimeLimitAction::Error),
+        ("tests/task/timeLimitYield", TimeLimitAction::Yield),
+    ] {
+        let script = read_to_string(format!("{script_name}.luau")).await?;
+        let exit_code = Lune::new()
+            .with_time_limit(Duration::from_millis(50), action)
+            .run(script_name, &script)
+            .await?;
+        assert_eq!(exit_code, ExitCode::SUCCESS, "{script_name} failed");
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_worker_exit() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    // Workers should be terminated and waited for once their parent has
+    // exited, and the worker used here writes to this file when it stops
+    let marker = Path::new("bin/temp-worker-exit");
+    if marker.exists() {
+        std::fs::remove_file(marker)?;
+    }
+    let script_name = "tests/task/workerExit";
+    let script = read_to_string(format!("{script_name}.luau")).await?;
+    let exit_code = Lune::new().run(script_name, &script).await?;
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(std::fs::read_to_string(marker)?, "stopped");
+    std::fs::remove_file(marker)?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn task_virtual_clock() -> Result<()> {
+    set_colors_enabled(false);
+    set_colors_enabled_stderr(false);
+    for script_name in ["tests/task/virtualClock", "tests/task/interval"] {
+        let script = read_to_string(format!("{script_name}.luau")).await?;
+        let exit_code = Lune::new()
+            .with_virtual_clock()
+            .run(script_name, &script)
+            .await?;
+        assert_eq!(exit_code, ExitCode::SUCCESS, "{script_name} failed");
+    }
+    Ok(())
 }
 
 #[cfg(feature = "roblox")]
@@ -0,0 +1,23 @@
+#![cfg(unix)]
+
+use std::process::Command;
+
+const SCRIPT: &str = "tests/process/signalExit.luau";
+
+#[test]
+fn signal_without_handler_runs_exit_hooks() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lune"))
+        .arg(SCRIPT)
+        .output()
+        .expect("Failed to run lune");
+    assert_eq!(
+        output.status.code(),
+        Some(128 + libc::SIGUSR1),
+        "Script should exit with the code for the signal\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("Exiting with code {}\n", 128 + libc::SIGUSR1)
+    );
+}
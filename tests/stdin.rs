@@ -0,0 +1,78 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+const SCRIPT: &str = "tests/stdio/stdin.luau";
+
+const BINARY: &[u8] = b"\x00\x01\xfe\xff\r\n\x00\nend";
+
+/**
+    Runs the stdin test script in the given mode, with the given data
+    piped to its stdin, and returns everything it wrote to stdout.
+*/
+fn run_with_stdin(mode: &str, input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lune"))
+        .arg(SCRIPT)
+        .arg(mode)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn lune");
+
+    // NOTE: The stdin handle must be dropped once written
+    // to, otherwise the script would never reach the end of input
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input).expect("Failed to write to stdin");
+    drop(stdin);
+
+    let output = child.wait_with_output().expect("Failed to wait for lune");
+    assert!(
+        output.status.success(),
+        "Script failed in mode '{mode}'\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+#[test]
+fn stdin_lines() {
+    let output = run_with_stdin("lines", b"first\nsecond\r\n\nlast");
+    assert_eq!(output, b"[first]\n[second]\n[]\n[last]\n");
+}
+
+#[test]
+fn stdin_read_line() {
+    let output = run_with_stdin("readLine", b"first\nsecond\r\n\nlast\n");
+    assert_eq!(output, b"[first]\n[second]\n[]\n[last]\n");
+}
+
+#[test]
+fn stdin_read() {
+    assert_eq!(run_with_stdin("read", BINARY), BINARY);
+}
+
+#[test]
+fn stdin_read_to_end() {
+    assert_eq!(run_with_stdin("readToEnd", BINARY), BINARY);
+}
+
+#[test]
+fn stdin_mixed() {
+    let output = run_with_stdin("mixed", b"one\ntwothree\nfour\nfive");
+    assert_eq!(output, b"[one]\n[two]\n[three]\n[four\nfive]\n");
+}
+
+#[test]
+fn stdin_empty() {
+    for mode in ["lines", "readLine", "read", "readToEnd"] {
+        assert_eq!(run_with_stdin(mode, b""), b"");
+    }
+}
+
+#[test]
+fn stdin_binary_lines() {
+    let output = run_with_stdin("lines", BINARY);
+    assert_eq!(output, b"[\x00\x01\xfe\xff]\n[\x00]\n[end]\n");
+}